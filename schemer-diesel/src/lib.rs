@@ -0,0 +1,424 @@
+//! An adapter enabling use of the schemer schema migration library with
+//! Diesel connections.
+//!
+//! Backends are enabled with the `postgres` and `sqlite` features, which are
+//! enabled by default, and the `mysql` feature.
+//!
+//! # Examples:
+//!
+//! ```rust
+//! extern crate diesel;
+//! #[macro_use]
+//! extern crate schemer;
+//! extern crate schemer_diesel;
+//! extern crate uuid;
+//!
+//! use std::collections::HashSet;
+//!
+//! use diesel::{Connection, RunQueryDsl, SqliteConnection};
+//! use schemer::{Migration, Migrator};
+//! use schemer_diesel::{DieselAdapter, DieselAdapterError, DieselMigration};
+//! use uuid::Uuid;
+//!
+//! struct MyExampleMigration;
+//! migration!(
+//!     MyExampleMigration,
+//!     "4885e8ab-dafa-4d76-a565-2dee8b04ef60",
+//!     [],
+//!     "An example migration without dependencies.");
+//!
+//! impl DieselMigration<SqliteConnection> for MyExampleMigration {
+//!     fn up(&self, conn: &mut SqliteConnection) -> Result<(), DieselAdapterError> {
+//!         diesel::sql_query("CREATE TABLE my_example (id integer PRIMARY KEY);").execute(conn)?;
+//!         Ok(())
+//!     }
+//!
+//!     fn down(&self, conn: &mut SqliteConnection) -> Result<(), DieselAdapterError> {
+//!         diesel::sql_query("DROP TABLE my_example;").execute(conn)?;
+//!         Ok(())
+//!     }
+//! }
+//!
+//! fn main() {
+//!     let mut conn = SqliteConnection::establish(":memory:").unwrap();
+//!     let mut adapter = DieselAdapter::new(&mut conn, None);
+//!     adapter.init().unwrap();
+//!
+//!     let mut migrator = Migrator::new(adapter);
+//!
+//!     let migration = Box::new(MyExampleMigration {});
+//!     migrator.register(migration);
+//!     migrator.up(None);
+//! }
+//! ```
+#![warn(clippy::all)]
+#![forbid(unsafe_code)]
+
+use diesel::result::Error as DieselError;
+use diesel::Connection;
+use uuid::Uuid;
+
+use schemer::Migration;
+
+/// Diesel-specific trait for schema migrations on connections of type `C`.
+pub trait DieselMigration<C: Connection>: Migration {
+    /// Apply a migration to the database using a connection inside a
+    /// transaction.
+    fn up(&self, _conn: &mut C) -> Result<(), DieselError> {
+        Ok(())
+    }
+
+    /// Revert a migration to the database using a connection inside a
+    /// transaction.
+    /// Does nothing by default; see [`Migration::reversible`].
+    fn down(&self, _conn: &mut C) -> Result<(), DieselError> {
+        Ok(())
+    }
+}
+
+pub type DieselAdapterError = DieselError;
+
+/// Conversion between migration IDs and the Rust type of the column a backend
+/// stores them in.
+trait StoredId: Sized {
+    fn from_uuid(id: Uuid) -> Self;
+
+    fn into_uuid(self) -> Result<Uuid, DieselError>;
+}
+
+#[cfg(feature = "postgres")]
+impl StoredId for Uuid {
+    fn from_uuid(id: Uuid) -> Self {
+        id
+    }
+
+    fn into_uuid(self) -> Result<Uuid, DieselError> {
+        Ok(self)
+    }
+}
+
+#[cfg(any(feature = "sqlite", feature = "mysql"))]
+impl StoredId for Vec<u8> {
+    fn from_uuid(id: Uuid) -> Self {
+        id.as_bytes().to_vec()
+    }
+
+    fn into_uuid(self) -> Result<Uuid, DieselError> {
+        Uuid::from_slice(&self).map_err(|e| DieselError::DeserializationError(Box::new(e)))
+    }
+}
+
+/// Adapter between schemer and a Diesel connection of type `C`.
+///
+/// `Adapter` is implemented for the connection types of each backend enabled
+/// by this crate's features. The metadata table stores only migration IDs and
+/// checksums: unlike `schemer-postgres`, `schemer-tokio-postgres` and
+/// `schemer-rusqlite`, this adapter keeps no schema version or history tables.
+/// Do not use it on the same database as those adapters, as migrations it runs
+/// would be missing from their history.
+pub struct DieselAdapter<'a, C: Connection> {
+    conn: &'a mut C,
+    migration_metadata_table: String,
+}
+
+impl<'a, C: Connection> DieselAdapter<'a, C> {
+    /// Construct a Diesel schemer adapter.
+    ///
+    /// `table_name` specifies the name of the table that schemer will use
+    /// for storing metadata about applied migrations. If `None`, a default
+    /// will be used.
+    pub fn new(conn: &'a mut C, table_name: Option<String>) -> DieselAdapter<'a, C> {
+        DieselAdapter {
+            conn,
+            migration_metadata_table: table_name.unwrap_or_else(|| "_schemer".into()),
+        }
+    }
+}
+
+/// Implement `init` and `Adapter` for a Diesel connection type, given the
+/// column type for migration IDs with its Diesel SQL and Rust types, and the
+/// bind parameter placeholders of its SQL dialect.
+macro_rules! impl_diesel_adapter {
+    (
+        $module:ident,
+        $conn:ty,
+        $id_type:literal,
+        $id_sql:ty,
+        $id_rust:ty,
+        $param1:literal,
+        $param2:literal
+    ) => {
+        mod $module {
+            use std::collections::{HashMap, HashSet};
+
+            use diesel::sql_types::{Nullable, Text};
+            use diesel::{Connection, QueryableByName, RunQueryDsl};
+            use schemer::Adapter;
+            use uuid::Uuid;
+
+            use super::{
+                DieselAdapter, DieselAdapterError, DieselError, DieselMigration, StoredId,
+            };
+
+            #[derive(QueryableByName)]
+            struct IdRow {
+                #[diesel(sql_type = $id_sql)]
+                id: $id_rust,
+            }
+
+            #[derive(QueryableByName)]
+            struct ChecksumRow {
+                #[diesel(sql_type = $id_sql)]
+                id: $id_rust,
+                #[diesel(sql_type = Text)]
+                checksum: String,
+            }
+
+            fn record_migration(
+                conn: &mut $conn,
+                table: &str,
+                migration: &dyn DieselMigration<$conn>,
+            ) -> Result<(), DieselError> {
+                diesel::sql_query(format!(
+                    "INSERT INTO {} (id, checksum) VALUES ({}, {});",
+                    table, $param1, $param2
+                ))
+                .bind::<$id_sql, _>(<$id_rust>::from_uuid(migration.id()))
+                .bind::<Nullable<Text>, _>(migration.checksum())
+                .execute(conn)?;
+                Ok(())
+            }
+
+            fn unrecord_migration(
+                conn: &mut $conn,
+                table: &str,
+                migration: &dyn DieselMigration<$conn>,
+            ) -> Result<(), DieselError> {
+                diesel::sql_query(format!("DELETE FROM {} WHERE id = {};", table, $param1))
+                    .bind::<$id_sql, _>(<$id_rust>::from_uuid(migration.id()))
+                    .execute(conn)?;
+                Ok(())
+            }
+
+            impl<'a> DieselAdapter<'a, $conn> {
+                /// Initialize the schemer metadata schema. This must be called
+                /// before using `Migrator` with this adapter. This is safe to
+                /// call multiple times.
+                pub fn init(&mut self) -> Result<(), DieselError> {
+                    diesel::sql_query(format!(
+                        r#"
+                            CREATE TABLE IF NOT EXISTS {} (
+                                id {} PRIMARY KEY,
+                                checksum text
+                            )
+                        "#,
+                        self.migration_metadata_table, $id_type
+                    ))
+                    .execute(self.conn)?;
+                    Ok(())
+                }
+            }
+
+            impl<'a> Adapter for DieselAdapter<'a, $conn> {
+                type MigrationType = dyn DieselMigration<$conn>;
+
+                type Error = DieselAdapterError;
+
+                fn applied_migrations(&mut self) -> Result<HashSet<Uuid>, Self::Error> {
+                    diesel::sql_query(format!("SELECT id FROM {};", self.migration_metadata_table))
+                        .load::<IdRow>(self.conn)?
+                        .into_iter()
+                        .map(|row| row.id.into_uuid())
+                        .collect()
+                }
+
+                fn apply_migration(
+                    &mut self,
+                    migration: &Self::MigrationType,
+                ) -> Result<(), Self::Error> {
+                    let table = &self.migration_metadata_table;
+                    self.conn.transaction(|conn| {
+                        migration.up(conn)?;
+                        record_migration(conn, table, migration)
+                    })
+                }
+
+                fn revert_migration(
+                    &mut self,
+                    migration: &Self::MigrationType,
+                ) -> Result<(), Self::Error> {
+                    let table = &self.migration_metadata_table;
+                    self.conn.transaction(|conn| {
+                        migration.down(conn)?;
+                        unrecord_migration(conn, table, migration)
+                    })
+                }
+
+                fn record_migration(
+                    &mut self,
+                    migration: &Self::MigrationType,
+                ) -> Result<(), Self::Error> {
+                    record_migration(self.conn, &self.migration_metadata_table, migration)
+                }
+
+                fn unrecord_migration(
+                    &mut self,
+                    migration: &Self::MigrationType,
+                ) -> Result<(), Self::Error> {
+                    unrecord_migration(self.conn, &self.migration_metadata_table, migration)
+                }
+
+                fn applied_checksums(&mut self) -> Result<HashMap<Uuid, String>, Self::Error> {
+                    diesel::sql_query(format!(
+                        "SELECT id, checksum FROM {} WHERE checksum IS NOT NULL;",
+                        self.migration_metadata_table
+                    ))
+                    .load::<ChecksumRow>(self.conn)?
+                    .into_iter()
+                    .map(|row| Ok((row.id.into_uuid()?, row.checksum)))
+                    .collect()
+                }
+            }
+        }
+    };
+}
+
+#[cfg(feature = "postgres")]
+impl_diesel_adapter!(
+    postgres,
+    diesel::PgConnection,
+    "uuid",
+    diesel::sql_types::Uuid,
+    Uuid,
+    "$1",
+    "$2"
+);
+
+#[cfg(feature = "sqlite")]
+impl_diesel_adapter!(
+    sqlite,
+    diesel::SqliteConnection,
+    "blob",
+    diesel::sql_types::Binary,
+    Vec<u8>,
+    "?",
+    "?"
+);
+
+#[cfg(feature = "mysql")]
+impl_diesel_adapter!(
+    mysql,
+    diesel::MysqlConnection,
+    "binary(16)",
+    diesel::sql_types::Binary,
+    Vec<u8>,
+    "?",
+    "?"
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use schemer::testing::*;
+    use schemer::{test_schemer_adapter, Adapter};
+
+    impl<C: Connection> DieselMigration<C> for TestMigration {}
+
+    impl<'a, C: Connection> TestAdapter for DieselAdapter<'a, C>
+    where
+        DieselAdapter<'a, C>: Adapter<MigrationType = dyn DieselMigration<C>>,
+    {
+        fn mock_from(migration: TestMigration) -> Box<Self::MigrationType> {
+            Box::new(migration)
+        }
+    }
+
+    #[cfg(feature = "postgres")]
+    mod postgres {
+        use super::*;
+        use diesel::{PgConnection, RunQueryDsl};
+
+        fn build_test_connection() -> PgConnection {
+            let mut conn = PgConnection::establish("postgresql://postgres@localhost").unwrap();
+            diesel::sql_query("SET search_path = pg_temp")
+                .execute(&mut conn)
+                .unwrap();
+            conn
+        }
+
+        fn build_test_adapter(conn: &mut PgConnection) -> DieselAdapter<'_, PgConnection> {
+            let mut adapter = DieselAdapter::new(conn, None);
+            adapter.init().unwrap();
+            adapter
+        }
+
+        test_schemer_adapter!(
+            let mut conn = build_test_connection(),
+            build_test_adapter(&mut conn));
+    }
+
+    #[cfg(feature = "sqlite")]
+    mod sqlite {
+        use super::*;
+        use diesel::SqliteConnection;
+
+        fn build_test_connection() -> SqliteConnection {
+            SqliteConnection::establish(":memory:").unwrap()
+        }
+
+        fn build_test_adapter(conn: &mut SqliteConnection) -> DieselAdapter<'_, SqliteConnection> {
+            let mut adapter = DieselAdapter::new(conn, None);
+            adapter.init().unwrap();
+            adapter
+        }
+
+        test_schemer_adapter!(
+            let mut conn = build_test_connection(),
+            build_test_adapter(&mut conn));
+    }
+
+    /// MySQL tests run against the database at `SCHEMER_MYSQL_URL`. They are
+    /// ignored by default, so run them with `--ignored`.
+    #[cfg(feature = "mysql")]
+    mod mysql {
+        use super::*;
+        use diesel::{MysqlConnection, RunQueryDsl};
+
+        /// Connection to the test database, dropping the metadata table of
+        /// the test using it when dropped.
+        struct TestDatabase {
+            conn: MysqlConnection,
+            table_name: String,
+        }
+
+        impl TestDatabase {
+            fn connect() -> TestDatabase {
+                let url = std::env::var("SCHEMER_MYSQL_URL")
+                    .expect("SCHEMER_MYSQL_URL must be set to run MySQL tests");
+                TestDatabase {
+                    conn: MysqlConnection::establish(&url).unwrap(),
+                    table_name: unique_table_name(),
+                }
+            }
+        }
+
+        impl Drop for TestDatabase {
+            fn drop(&mut self) {
+                // Panicking here while a failed test unwinds would abort the run.
+                let _ = diesel::sql_query(format!("DROP TABLE IF EXISTS {}", self.table_name))
+                    .execute(&mut self.conn);
+            }
+        }
+
+        fn build_test_adapter(db: &mut TestDatabase) -> DieselAdapter<'_, MysqlConnection> {
+            let mut adapter = DieselAdapter::new(&mut db.conn, Some(db.table_name.clone()));
+            adapter.init().unwrap();
+            adapter
+        }
+
+        test_schemer_adapter!(
+            #[ignore = "requires a MySQL database at SCHEMER_MYSQL_URL"]
+            let mut db = TestDatabase::connect(),
+            build_test_adapter(&mut db));
+    }
+}
@@ -0,0 +1,296 @@
+//! An adapter enabling use of the schemer schema migration library with
+//! MySQL and MariaDB.
+//!
+//! # Transactions and partial failure
+//!
+//! Unlike PostgreSQL and SQLite, MySQL and MariaDB can not run DDL statements
+//! such as `CREATE TABLE` or `ALTER TABLE` inside a transaction: each causes
+//! an implicit commit of the current transaction before and after it is
+//! executed. A migration changing the schema can therefore not be applied or
+//! reverted atomically, and the adapter does not pretend to.
+//!
+//! Each migration instead declares with `MysqlMigration::transactional`
+//! whether it should be run in a transaction:
+//!
+//! - A transactional migration is run, along with updating schemer's metadata,
+//!   inside a single transaction that is rolled back if the migration fails.
+//!   This is atomic only if the migration issues no statements causing an
+//!   implicit commit, so it is intended for migrations that change data
+//!   rather than schema.
+//! - A non-transactional migration, the default, is run directly on the
+//!   connection, and schemer's metadata is updated only once it succeeds. If
+//!   it fails partway, the statements it already executed remain in effect
+//!   while the migration is still recorded as not applied (or, when
+//!   reverting, as applied). The database must then be repaired by hand, for
+//!   example by completing or undoing the migration's remaining statements
+//!   and using `Migrator::mark_applied` or `Migrator::mark_reverted`.
+//!
+//! Keeping each non-transactional migration to a single DDL statement, or to
+//! statements that are each safe to re-run, such as `CREATE TABLE IF NOT
+//! EXISTS`, limits what can be left behind by a failure.
+//!
+//! # Examples:
+//!
+//! ```rust,no_run
+//! extern crate mysql;
+//! #[macro_use]
+//! extern crate schemer;
+//! extern crate schemer_mysql;
+//! extern crate uuid;
+//!
+//! use std::collections::HashSet;
+//!
+//! use mysql::prelude::Queryable;
+//! use mysql::Conn;
+//! use schemer::{Migration, Migrator};
+//! use schemer_mysql::{MysqlAdapter, MysqlAdapterError, MysqlMigration};
+//! use uuid::Uuid;
+//!
+//! struct MyExampleMigration;
+//! migration!(
+//!     MyExampleMigration,
+//!     "4885e8ab-dafa-4d76-a565-2dee8b04ef60",
+//!     [],
+//!     "An example migration without dependencies.");
+//!
+//! impl MysqlMigration for MyExampleMigration {
+//!     fn up(&self, conn: &mut Conn) -> Result<(), MysqlAdapterError> {
+//!         conn.query_drop("CREATE TABLE my_example (id integer PRIMARY KEY);")
+//!     }
+//!
+//!     fn down(&self, conn: &mut Conn) -> Result<(), MysqlAdapterError> {
+//!         conn.query_drop("DROP TABLE my_example;")
+//!     }
+//! }
+//!
+//! fn main() {
+//!     let mut conn = Conn::new("mysql://root@localhost/example").unwrap();
+//!     let mut adapter = MysqlAdapter::new(&mut conn, None);
+//!     adapter.init().unwrap();
+//!
+//!     let mut migrator = Migrator::new(adapter);
+//!
+//!     let migration = Box::new(MyExampleMigration {});
+//!     migrator.register(migration);
+//!     migrator.up(None);
+//! }
+//! ```
+#![warn(clippy::all)]
+#![forbid(unsafe_code)]
+
+use std::collections::{HashMap, HashSet};
+
+use mysql::prelude::Queryable;
+use mysql::{Conn, Error as MysqlError};
+use uuid::Uuid;
+
+use schemer::{Adapter, Migration};
+
+/// MySQL-specific trait for schema migrations.
+///
+/// See the crate documentation for how `transactional` affects what happens
+/// when a migration fails.
+pub trait MysqlMigration: Migration {
+    /// Apply a migration to the database using a connection, which is inside
+    /// a transaction if this migration is `transactional`.
+    fn up(&self, _conn: &mut Conn) -> Result<(), MysqlError> {
+        Ok(())
+    }
+
+    /// Revert a migration to the database using a connection, which is inside
+    /// a transaction if this migration is `transactional`.
+    /// Does nothing by default; see [`Migration::reversible`].
+    fn down(&self, _conn: &mut Conn) -> Result<(), MysqlError> {
+        Ok(())
+    }
+
+    /// Whether this migration is run in a transaction. This should only be
+    /// `true` if `up` and `down` execute no statements causing an implicit
+    /// commit, such as DDL, as otherwise the transaction is committed partway.
+    fn transactional(&self) -> bool {
+        false
+    }
+}
+
+pub type MysqlAdapterError = MysqlError;
+
+fn parse_uuid(bytes: Vec<u8>) -> Result<Uuid, MysqlError> {
+    Uuid::from_slice(&bytes).map_err(|_| MysqlError::FromValueError(mysql::Value::Bytes(bytes)))
+}
+
+/// Adapter between schemer and MySQL or MariaDB.
+pub struct MysqlAdapter<'a> {
+    conn: &'a mut Conn,
+    migration_metadata_table: String,
+}
+
+impl<'a> MysqlAdapter<'a> {
+    /// Construct a MySQL schemer adapter.
+    ///
+    /// `table_name` specifies the name of the table that schemer will use
+    /// for storing metadata about applied migrations. If `None`, a default
+    /// will be used.
+    pub fn new(conn: &'a mut Conn, table_name: Option<String>) -> MysqlAdapter<'a> {
+        MysqlAdapter {
+            conn,
+            migration_metadata_table: table_name.unwrap_or_else(|| "_schemer".into()),
+        }
+    }
+
+    /// Initialize the schemer metadata schema. This must be called before
+    /// using `Migrator` with this adapter. This is safe to call multiple times.
+    pub fn init(&mut self) -> Result<(), MysqlError> {
+        self.conn.query_drop(format!(
+            r#"
+                CREATE TABLE IF NOT EXISTS {} (
+                    id binary(16) PRIMARY KEY,
+                    checksum text
+                )
+            "#,
+            self.migration_metadata_table
+        ))
+    }
+
+    /// Run `f`, which executes `migration` and updates the metadata, in a
+    /// transaction if the migration is transactional.
+    fn run<F>(&mut self, migration: &dyn MysqlMigration, f: F) -> Result<(), MysqlError>
+    where
+        F: FnOnce(&mut Conn, &str) -> Result<(), MysqlError>,
+    {
+        if !migration.transactional() {
+            return f(self.conn, &self.migration_metadata_table);
+        }
+
+        self.conn.query_drop("START TRANSACTION")?;
+        match f(self.conn, &self.migration_metadata_table) {
+            Ok(()) => self.conn.query_drop("COMMIT"),
+            Err(e) => {
+                // The original error is more useful than any from rolling back.
+                let _ = self.conn.query_drop("ROLLBACK");
+                Err(e)
+            }
+        }
+    }
+}
+
+fn record_migration(
+    conn: &mut Conn,
+    table: &str,
+    migration: &dyn MysqlMigration,
+) -> Result<(), MysqlError> {
+    conn.exec_drop(
+        format!("INSERT INTO {} (id, checksum) VALUES (?, ?);", table),
+        (migration.id().as_bytes().to_vec(), migration.checksum()),
+    )
+}
+
+fn unrecord_migration(
+    conn: &mut Conn,
+    table: &str,
+    migration: &dyn MysqlMigration,
+) -> Result<(), MysqlError> {
+    conn.exec_drop(
+        format!("DELETE FROM {} WHERE id = ?;", table),
+        (migration.id().as_bytes().to_vec(),),
+    )
+}
+
+impl<'a> Adapter for MysqlAdapter<'a> {
+    type MigrationType = dyn MysqlMigration;
+
+    type Error = MysqlAdapterError;
+
+    fn applied_migrations(&mut self) -> Result<HashSet<Uuid>, Self::Error> {
+        let ids: Vec<Vec<u8>> = self
+            .conn
+            .query(format!("SELECT id FROM {};", self.migration_metadata_table))?;
+        ids.into_iter().map(parse_uuid).collect()
+    }
+
+    fn apply_migration(&mut self, migration: &Self::MigrationType) -> Result<(), Self::Error> {
+        self.run(migration, |conn, table| {
+            migration.up(conn)?;
+            record_migration(conn, table, migration)
+        })
+    }
+
+    fn revert_migration(&mut self, migration: &Self::MigrationType) -> Result<(), Self::Error> {
+        self.run(migration, |conn, table| {
+            migration.down(conn)?;
+            unrecord_migration(conn, table, migration)
+        })
+    }
+
+    fn record_migration(&mut self, migration: &Self::MigrationType) -> Result<(), Self::Error> {
+        record_migration(self.conn, &self.migration_metadata_table, migration)
+    }
+
+    fn unrecord_migration(&mut self, migration: &Self::MigrationType) -> Result<(), Self::Error> {
+        unrecord_migration(self.conn, &self.migration_metadata_table, migration)
+    }
+
+    fn applied_checksums(&mut self) -> Result<HashMap<Uuid, String>, Self::Error> {
+        let rows: Vec<(Vec<u8>, String)> = self.conn.query(format!(
+            "SELECT id, checksum FROM {} WHERE checksum IS NOT NULL;",
+            self.migration_metadata_table
+        ))?;
+        rows.into_iter()
+            .map(|(id, checksum)| Ok((parse_uuid(id)?, checksum)))
+            .collect()
+    }
+}
+
+/// Tests run against the database at `SCHEMER_MYSQL_URL`, such as a local
+/// MariaDB. They are ignored by default, so run them with `--ignored`.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use schemer::test_schemer_adapter;
+    use schemer::testing::*;
+
+    impl MysqlMigration for TestMigration {}
+
+    impl<'a> TestAdapter for MysqlAdapter<'a> {
+        fn mock_from(migration: TestMigration) -> Box<Self::MigrationType> {
+            Box::new(migration)
+        }
+    }
+
+    /// Connection to the test database, dropping the metadata table of the
+    /// test using it when dropped.
+    struct TestDatabase {
+        conn: Conn,
+        table_name: String,
+    }
+
+    impl TestDatabase {
+        fn connect() -> TestDatabase {
+            let url = std::env::var("SCHEMER_MYSQL_URL")
+                .expect("SCHEMER_MYSQL_URL must be set to run MySQL tests");
+            TestDatabase {
+                conn: Conn::new(url.as_str()).unwrap(),
+                table_name: unique_table_name(),
+            }
+        }
+    }
+
+    impl Drop for TestDatabase {
+        fn drop(&mut self) {
+            // Panicking here while a failed test unwinds would abort the run.
+            let _ = self
+                .conn
+                .query_drop(format!("DROP TABLE IF EXISTS {}", self.table_name));
+        }
+    }
+
+    fn build_test_adapter(db: &mut TestDatabase) -> MysqlAdapter<'_> {
+        let mut adapter = MysqlAdapter::new(&mut db.conn, Some(db.table_name.clone()));
+        adapter.init().unwrap();
+        adapter
+    }
+
+    test_schemer_adapter!(
+        #[ignore = "requires a MySQL database at SCHEMER_MYSQL_URL"]
+        let mut db = TestDatabase::connect(),
+        build_test_adapter(&mut db));
+}
@@ -53,12 +53,14 @@
 #![warn(clippy::all)]
 #![forbid(unsafe_code)]
 
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant, SystemTime};
 
-use postgres::{Client, Error as PostgresError, Transaction};
+use log::warn;
+use postgres::{Client, Error as PostgresError, GenericClient, Transaction};
 use uuid::Uuid;
 
-use schemer::{Adapter, Migration};
+use schemer::{Adapter, AppliedMigration, Migration, MigrationDirection, MigrationEvent};
 
 /// PostgreSQL-specific trait for schema migrations.
 pub trait PostgresMigration: Migration {
@@ -68,6 +70,7 @@ pub trait PostgresMigration: Migration {
     }
 
     /// Revert a migration to the database using a transaction.
+    /// Does nothing by default; see [`Migration::reversible`].
     fn down(&self, _transaction: &mut Transaction<'_>) -> Result<(), PostgresError> {
         Ok(())
     }
@@ -75,10 +78,20 @@ pub trait PostgresMigration: Migration {
 
 pub type PostgresAdapterError = PostgresError;
 
+/// Key of the advisory lock held while migrating, unless configured otherwise
+/// with `PostgresAdapter::set_advisory_lock`.
+pub const DEFAULT_ADVISORY_LOCK: i64 = 0x0073_6368_656d_6572;
+
 /// Adapter between schemer and PostgreSQL.
+///
+/// While migrating up or down, the adapter holds a session-level advisory
+/// lock, so that processes migrating the same database concurrently wait for
+/// each other and each migration is applied once.
 pub struct PostgresAdapter<'a> {
     conn: &'a mut Client,
     migration_metadata_table: String,
+    advisory_lock: Option<i64>,
+    applied_by: Option<String>,
 }
 
 impl<'a> PostgresAdapter<'a> {
@@ -103,27 +116,267 @@ impl<'a> PostgresAdapter<'a> {
         PostgresAdapter {
             conn,
             migration_metadata_table: table_name.unwrap_or_else(|| "_schemer".into()),
+            advisory_lock: Some(DEFAULT_ADVISORY_LOCK),
+            applied_by: None,
         }
     }
 
+    /// Set who is recorded as applying migrations, such as the application's
+    /// version or the host name. Defaults to `None`.
+    pub fn set_applied_by(&mut self, applied_by: Option<String>) {
+        self.applied_by = applied_by;
+    }
+
+    /// Set the key of the advisory lock held while migrating, or disable
+    /// locking if `None`. Defaults to `DEFAULT_ADVISORY_LOCK`.
+    ///
+    /// Migrators with different metadata tables in the same database only
+    /// need distinct keys if they should be able to migrate concurrently.
+    pub fn set_advisory_lock(&mut self, key: Option<i64>) {
+        self.advisory_lock = key;
+    }
+
     /// Initialize the schemer metadata schema. This must be called before
     /// using `Migrator` with this adapter. This is safe to call multiple times.
+    ///
+    /// The version of the metadata schema is stored in a table named after
+    /// the metadata table with a `_version` suffix. Metadata tables created by
+    /// earlier versions of this crate are upgraded in place, keeping their
+    /// records. Tables of a later version are left unchanged.
     pub fn init(&mut self) -> Result<(), PostgresError> {
-        self.conn.execute(
+        let table = &self.migration_metadata_table;
+        let mut trans = self.conn.transaction()?;
+        // Concurrent initializations would otherwise race to create tables.
+        if let Some(key) = self.advisory_lock {
+            trans.execute("SELECT pg_advisory_xact_lock($1)", &[&key])?;
+        }
+        trans.execute(
             format!(
-                r#"
-                    CREATE TABLE IF NOT EXISTS {} (
-                        id uuid PRIMARY KEY
-                    ) WITH (
-                        OIDS=FALSE
-                    )
-                "#,
-                self.migration_metadata_table
+                "CREATE TABLE IF NOT EXISTS {}_version (version integer NOT NULL)",
+                table
             )
             .as_str(),
             &[],
         )?;
-        Ok(())
+
+        let version: i32 = match trans.query_opt(
+            format!("SELECT version FROM {}_version", table).as_str(),
+            &[],
+        )? {
+            Some(row) => row.get(0),
+            None => {
+                let exists: bool = trans
+                    .query_one("SELECT to_regclass($1) IS NOT NULL", &[table])?
+                    .get(0);
+                // Tables predating versioning are version 1. Otherwise create
+                // the version 1 table, so that new tables are upgraded in the
+                // same way as existing ones.
+                if !exists {
+                    trans.execute(
+                        format!(
+                            r#"
+                                CREATE TABLE {} (
+                                    id uuid PRIMARY KEY
+                                ) WITH (
+                                    OIDS=FALSE
+                                )
+                            "#,
+                            table
+                        )
+                        .as_str(),
+                        &[],
+                    )?;
+                }
+                trans.execute(
+                    format!("INSERT INTO {}_version (version) VALUES (1)", table).as_str(),
+                    &[],
+                )?;
+                1
+            }
+        };
+
+        if version < METADATA_VERSION {
+            for from in version..METADATA_VERSION {
+                trans.batch_execute(&metadata_upgrade(table, from))?;
+            }
+            trans.execute(
+                format!("UPDATE {}_version SET version = $1", table).as_str(),
+                &[&METADATA_VERSION],
+            )?;
+        }
+        trans.commit()
+    }
+}
+
+/// Version of the metadata schema created by `PostgresAdapter::init`.
+const METADATA_VERSION: i32 = 3;
+
+/// SQL upgrading the metadata table `table` from version `from` to the next.
+fn metadata_upgrade(table: &str, from: i32) -> String {
+    match from {
+        // Tables of schemer-postgres 0.2 and earlier have only an `id`
+        // column.
+        1 => format!(
+            r#"
+                ALTER TABLE {}
+                    ADD COLUMN checksum text,
+                    ADD COLUMN description text,
+                    ADD COLUMN applied_at timestamptz,
+                    ADD COLUMN duration_us bigint,
+                    ADD COLUMN applied_by text;
+            "#,
+            table
+        ),
+        2 => format!(
+            r#"
+                CREATE TABLE IF NOT EXISTS {}_history (
+                    seq bigserial PRIMARY KEY,
+                    id uuid NOT NULL,
+                    direction text NOT NULL,
+                    marked boolean NOT NULL,
+                    occurred_at timestamptz NOT NULL,
+                    error text,
+                    performed_by text
+                );
+            "#,
+            table
+        ),
+        _ => unreachable!("No upgrade from metadata version {}", from),
+    }
+}
+
+fn record_migration<C: GenericClient>(
+    client: &mut C,
+    table: &str,
+    migration: &dyn PostgresMigration,
+    duration: Option<Duration>,
+    applied_by: Option<&str>,
+) -> Result<(), PostgresError> {
+    let duration_us = duration.map(|d| d.as_micros() as i64);
+    client.execute(
+        format!(
+            r#"
+                INSERT INTO {} (id, checksum, description, applied_at, duration_us, applied_by)
+                VALUES ($1::uuid, $2, $3, now(), $4, $5);
+            "#,
+            table
+        )
+        .as_str(),
+        &[
+            &migration.id(),
+            &migration.checksum(),
+            &migration.description(),
+            &duration_us,
+            &applied_by,
+        ],
+    )?;
+    Ok(())
+}
+
+fn unrecord_migration<C: GenericClient>(
+    client: &mut C,
+    table: &str,
+    migration: &dyn PostgresMigration,
+) -> Result<(), PostgresError> {
+    client.execute(
+        format!("DELETE FROM {} WHERE id = $1::uuid;", table).as_str(),
+        &[&migration.id()],
+    )?;
+    Ok(())
+}
+
+/// Record an attempt to apply, revert, or mark `migration` in the history
+/// table of the metadata table `table`.
+fn record_event<C: GenericClient>(
+    client: &mut C,
+    table: &str,
+    migration: &dyn PostgresMigration,
+    direction: MigrationDirection,
+    marked: bool,
+    error: Option<&str>,
+    performed_by: Option<&str>,
+) -> Result<(), PostgresError> {
+    let direction = match direction {
+        MigrationDirection::Up => "up",
+        MigrationDirection::Down => "down",
+    };
+    client.execute(
+        format!(
+            r#"
+                INSERT INTO {}_history (id, direction, marked, occurred_at, error, performed_by)
+                VALUES ($1::uuid, $2, $3, now(), $4, $5);
+            "#,
+            table
+        )
+        .as_str(),
+        &[&migration.id(), &direction, &marked, &error, &performed_by],
+    )?;
+    Ok(())
+}
+
+/// Describe `error` along with its sources, which for database errors hold
+/// the message from the server.
+fn error_text(error: &dyn std::error::Error) -> String {
+    let mut text = error.to_string();
+    let mut source = error.source();
+    while let Some(e) = source {
+        text.push_str(": ");
+        text.push_str(&e.to_string());
+        source = e.source();
+    }
+    text
+}
+
+impl<'a> PostgresAdapter<'a> {
+    /// Run `f` in a transaction and record its outcome in the history table.
+    /// Success is recorded in the same transaction, while failure is recorded
+    /// after the transaction is rolled back, logging a warning if this fails.
+    fn run_recorded<F>(
+        &mut self,
+        migration: &dyn PostgresMigration,
+        direction: MigrationDirection,
+        marked: bool,
+        f: F,
+    ) -> Result<(), PostgresError>
+    where
+        F: FnOnce(&mut Transaction<'_>, &str, Option<&str>) -> Result<(), PostgresError>,
+    {
+        let table = &self.migration_metadata_table;
+        let performed_by = self.applied_by.as_deref();
+        let result = (|| {
+            let mut trans = self.conn.transaction()?;
+            f(&mut trans, table, performed_by)?;
+            record_event(
+                &mut trans,
+                table,
+                migration,
+                direction,
+                marked,
+                None,
+                performed_by,
+            )?;
+            trans.commit()
+        })();
+        if let Err(e) = &result {
+            // The error of the attempt itself is returned, so one from
+            // recording it can only be logged.
+            if let Err(history_error) = record_event(
+                self.conn,
+                table,
+                migration,
+                direction,
+                marked,
+                Some(&error_text(e)),
+                performed_by,
+            ) {
+                warn!(
+                    "Failed to record failure of migration {} in history: {}",
+                    migration.id(),
+                    history_error
+                );
+            }
+        }
+        result
     }
 }
 
@@ -141,31 +394,130 @@ impl<'a> Adapter for PostgresAdapter<'a> {
     }
 
     fn apply_migration(&mut self, migration: &Self::MigrationType) -> Result<(), Self::Error> {
-        let mut trans = self.conn.transaction()?;
-        migration.up(&mut trans)?;
-        trans.execute(
+        self.run_recorded(
+            migration,
+            MigrationDirection::Up,
+            false,
+            |trans, table, applied_by| {
+                let start = Instant::now();
+                migration.up(trans)?;
+                record_migration(trans, table, migration, Some(start.elapsed()), applied_by)
+            },
+        )
+    }
+
+    fn revert_migration(&mut self, migration: &Self::MigrationType) -> Result<(), Self::Error> {
+        self.run_recorded(
+            migration,
+            MigrationDirection::Down,
+            false,
+            |trans, table, _| {
+                migration.down(trans)?;
+                unrecord_migration(trans, table, migration)
+            },
+        )
+    }
+
+    fn record_migration(&mut self, migration: &Self::MigrationType) -> Result<(), Self::Error> {
+        self.run_recorded(
+            migration,
+            MigrationDirection::Up,
+            true,
+            |trans, table, applied_by| record_migration(trans, table, migration, None, applied_by),
+        )
+    }
+
+    fn unrecord_migration(&mut self, migration: &Self::MigrationType) -> Result<(), Self::Error> {
+        self.run_recorded(
+            migration,
+            MigrationDirection::Down,
+            true,
+            |trans, table, _| unrecord_migration(trans, table, migration),
+        )
+    }
+
+    fn applied_checksums(&mut self) -> Result<HashMap<Uuid, String>, Self::Error> {
+        let rows = self.conn.query(
             format!(
-                "INSERT INTO {} (id) VALUES ($1::uuid);",
+                "SELECT id, checksum FROM {} WHERE checksum IS NOT NULL;",
                 self.migration_metadata_table
             )
             .as_str(),
-            &[&migration.id()],
+            &[],
         )?;
-        trans.commit()
+        Ok(rows.iter().map(|row| (row.get(0), row.get(1))).collect())
     }
 
-    fn revert_migration(&mut self, migration: &Self::MigrationType) -> Result<(), Self::Error> {
-        let mut trans = self.conn.transaction()?;
-        migration.down(&mut trans)?;
-        trans.execute(
+    fn applied_records(&mut self) -> Result<Vec<AppliedMigration>, Self::Error> {
+        let rows = self.conn.query(
             format!(
-                "DELETE FROM {} WHERE id = $1::uuid;",
+                r#"
+                    SELECT id, checksum, description, applied_at, duration_us, applied_by
+                    FROM {}
+                    ORDER BY applied_at, id;
+                "#,
                 self.migration_metadata_table
             )
             .as_str(),
-            &[&migration.id()],
+            &[],
         )?;
-        trans.commit()
+        Ok(rows
+            .iter()
+            .map(|row| AppliedMigration {
+                id: row.get(0),
+                checksum: row.get(1),
+                description: row.get(2),
+                applied_at: row.get::<_, Option<SystemTime>>(3),
+                duration: row
+                    .get::<_, Option<i64>>(4)
+                    .map(|us| Duration::from_micros(us as u64)),
+                applied_by: row.get(5),
+            })
+            .collect())
+    }
+
+    fn history(&mut self) -> Result<Vec<MigrationEvent>, Self::Error> {
+        let rows = self.conn.query(
+            format!(
+                r#"
+                    SELECT id, direction, marked, occurred_at, error, performed_by
+                    FROM {}_history
+                    ORDER BY seq;
+                "#,
+                self.migration_metadata_table
+            )
+            .as_str(),
+            &[],
+        )?;
+        Ok(rows
+            .iter()
+            .map(|row| MigrationEvent {
+                id: row.get(0),
+                direction: match row.get(1) {
+                    "down" => MigrationDirection::Down,
+                    _ => MigrationDirection::Up,
+                },
+                marked: row.get(2),
+                occurred_at: row.get(3),
+                error: row.get(4),
+                performed_by: row.get(5),
+            })
+            .collect())
+    }
+
+    fn lock(&mut self) -> Result<(), Self::Error> {
+        if let Some(key) = self.advisory_lock {
+            self.conn.execute("SELECT pg_advisory_lock($1)", &[&key])?;
+        }
+        Ok(())
+    }
+
+    fn unlock(&mut self) -> Result<(), Self::Error> {
+        if let Some(key) = self.advisory_lock {
+            self.conn
+                .execute("SELECT pg_advisory_unlock($1)", &[&key])?;
+        }
+        Ok(())
     }
 }
 
@@ -173,14 +525,28 @@ impl<'a> Adapter for PostgresAdapter<'a> {
 mod tests {
     use super::*;
     use postgres::NoTls;
-    use schemer::test_schemer_adapter;
     use schemer::testing::*;
+    use schemer::{migration, test_schemer_adapter, Migrator};
 
     impl PostgresMigration for TestMigration {}
 
+    struct FailingMigration;
+    migration!(
+        FailingMigration,
+        "4885e8ab-dafa-4d76-a565-2dee8b04ef60",
+        ["bc960dc8-0e4a-4182-a62a-8e776d1e2b30"],
+        "A migration that fails to apply."
+    );
+
+    impl PostgresMigration for FailingMigration {
+        fn up(&self, transaction: &mut Transaction<'_>) -> Result<(), PostgresError> {
+            transaction.batch_execute("SELECT * FROM nonexistent;")
+        }
+    }
+
     impl<'a> TestAdapter for PostgresAdapter<'a> {
-        fn mock(id: Uuid, dependencies: HashSet<Uuid>) -> Box<Self::MigrationType> {
-            Box::new(TestMigration::new(id, dependencies))
+        fn mock_from(migration: TestMigration) -> Box<Self::MigrationType> {
+            Box::new(migration)
         }
     }
 
@@ -199,4 +565,133 @@ mod tests {
     test_schemer_adapter!(
         let mut conn = build_test_connection(),
         build_test_adapter(&mut conn));
+
+    #[test]
+    fn test_advisory_lock() {
+        let key = 0x7465_7374_6b65_7900;
+        let mut conn = build_test_connection();
+        let mut other_conn = build_test_connection();
+        let try_lock = "SELECT pg_try_advisory_lock($1)";
+
+        let mut adapter = build_test_adapter(&mut conn);
+        adapter.set_advisory_lock(Some(key));
+        adapter.lock().unwrap();
+        let acquired: bool = other_conn.query_one(try_lock, &[&key]).unwrap().get(0);
+        assert!(!acquired);
+
+        adapter.unlock().unwrap();
+        let acquired: bool = other_conn.query_one(try_lock, &[&key]).unwrap().get(0);
+        assert!(acquired);
+    }
+
+    #[test]
+    fn test_upgrade_metadata() {
+        let mut conn = build_test_connection();
+        let id = Uuid::parse_str("bc960dc8-0e4a-4182-a62a-8e776d1e2b30").unwrap();
+        conn.batch_execute("CREATE TABLE _schemer (id uuid PRIMARY KEY);")
+            .unwrap();
+        conn.execute("INSERT INTO _schemer (id) VALUES ($1)", &[&id])
+            .unwrap();
+
+        let mut adapter = build_test_adapter(&mut conn);
+        adapter.init().unwrap();
+        assert_eq!(
+            adapter.applied_records().unwrap(),
+            vec![AppliedMigration::new(id)]
+        );
+        let migration = PostgresAdapter::mock(
+            Uuid::parse_str("4885e8ab-dafa-4d76-a565-2dee8b04ef60").unwrap(),
+            HashSet::new(),
+        );
+        adapter.apply_migration(migration.as_ref()).unwrap();
+        let records = adapter.applied_records().unwrap();
+        assert_eq!(records.len(), 2);
+        assert!(records[0].applied_at.is_some());
+
+        let version: i32 = conn
+            .query_one("SELECT version FROM _schemer_version", &[])
+            .unwrap()
+            .get(0);
+        assert_eq!(version, METADATA_VERSION);
+    }
+
+    #[test]
+    fn test_history() {
+        let mut conn = build_test_connection();
+        let mut adapter = build_test_adapter(&mut conn);
+        adapter.set_applied_by(Some("test".into()));
+        let id = Uuid::parse_str("bc960dc8-0e4a-4182-a62a-8e776d1e2b30").unwrap();
+        let mut migrator = Migrator::new(adapter);
+        migrator
+            .register_multiple(vec![
+                PostgresAdapter::mock(id, HashSet::new()),
+                Box::new(FailingMigration),
+            ])
+            .unwrap();
+
+        assert!(migrator.up(None).is_err());
+        migrator.down(None).unwrap();
+        migrator.mark_applied(&[id]).unwrap();
+
+        let history = migrator.history().unwrap();
+        let events: Vec<_> = history
+            .iter()
+            .map(|event| {
+                (
+                    event.id,
+                    event.direction,
+                    event.marked,
+                    event.error.is_some(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                (id, MigrationDirection::Up, false, false),
+                (FailingMigration.id(), MigrationDirection::Up, false, true),
+                (id, MigrationDirection::Down, false, false),
+                (id, MigrationDirection::Up, true, false),
+            ]
+        );
+        assert!(history[1].error.as_ref().unwrap().contains("nonexistent"));
+        assert!(history
+            .iter()
+            .all(|event| event.performed_by.as_deref() == Some("test")));
+    }
+
+    #[test]
+    fn test_history_failure() {
+        let mut conn = build_test_connection();
+        let mut adapter = build_test_adapter(&mut conn);
+        adapter
+            .conn
+            .batch_execute("DROP TABLE _schemer_history;")
+            .unwrap();
+
+        let error = adapter.apply_migration(&FailingMigration).unwrap_err();
+        assert!(error_text(&error).contains("nonexistent"));
+        assert!(adapter.history().is_err());
+        assert!(adapter.applied_migrations().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_applied_record_metadata() {
+        let mut conn = build_test_connection();
+        let mut adapter = build_test_adapter(&mut conn);
+        adapter.set_applied_by(Some("test".into()));
+        let id = Uuid::parse_str("bc960dc8-0e4a-4182-a62a-8e776d1e2b30").unwrap();
+        let migration = PostgresAdapter::mock(id, HashSet::new());
+        let before = SystemTime::now() - Duration::from_secs(60);
+
+        adapter.apply_migration(migration.as_ref()).unwrap();
+        let records = adapter.applied_records().unwrap();
+        assert_eq!(records.len(), 1);
+        let record = &records[0];
+        assert_eq!(record.id, migration.id());
+        assert_eq!(record.description.as_deref(), Some("Test Migration"));
+        assert!(record.applied_at.unwrap() > before);
+        assert!(record.duration.is_some());
+        assert_eq!(record.applied_by.as_deref(), Some("test"));
+    }
 }
@@ -52,14 +52,20 @@
 #![warn(clippy::all)]
 #![forbid(unsafe_code)]
 
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
 use std::marker::{PhantomData, Send, Sync};
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use rusqlite::{params, Connection, Error as RusqliteError, Transaction};
+use log::warn;
+use rusqlite::{
+    params, Connection, Error as RusqliteError, OptionalExtension, Transaction, TransactionBehavior,
+};
 use uuid::Uuid;
 
-use schemer::{Adapter, Migration};
+use schemer::{Adapter, AppliedMigration, Migration, MigrationDirection, MigrationEvent};
 
 /// SQlite-specific trait for schema migrations.
 pub trait RusqliteMigration: Migration {
@@ -71,6 +77,7 @@ pub trait RusqliteMigration: Migration {
     }
 
     /// Revert a migration to the database using a transaction.
+    /// Does nothing by default; see [`Migration::reversible`].
     fn down(&self, _transaction: &Transaction<'_>) -> Result<(), Self::Error> {
         Ok(())
     }
@@ -88,10 +95,68 @@ impl rusqlite::types::FromSql for WrappedUuid {
     }
 }
 
+fn record_migration<M: Migration + ?Sized>(
+    conn: &Connection,
+    table: &str,
+    migration: &M,
+    duration: Option<Duration>,
+    applied_by: Option<&str>,
+) -> Result<(), RusqliteError> {
+    let uuid = migration.id();
+    let uuid_bytes = &uuid.as_bytes()[..];
+    let duration_us = duration.map(|d| d.as_micros() as i64);
+    conn.execute(
+        &format!(
+            r#"
+                INSERT INTO {} (id, checksum, description, applied_at, duration_us, applied_by)
+                VALUES (?1, ?2, ?3, strftime('%Y-%m-%d %H:%M:%f', 'now'), ?4, ?5);
+            "#,
+            table
+        ),
+        params![
+            uuid_bytes,
+            migration.checksum(),
+            migration.description(),
+            duration_us,
+            applied_by
+        ],
+    )?;
+    Ok(())
+}
+
+fn unrecord_migration<M: Migration + ?Sized>(
+    conn: &Connection,
+    table: &str,
+    migration: &M,
+) -> Result<(), RusqliteError> {
+    let uuid = migration.id();
+    let uuid_bytes = &uuid.as_bytes()[..];
+    conn.execute(
+        &format!("DELETE FROM {} WHERE id = ?1;", table),
+        [&uuid_bytes],
+    )?;
+    Ok(())
+}
+
+/// Busy handler waiting indefinitely for the run lock.
+fn wait_for_lock(_attempts: i32) -> bool {
+    thread::sleep(Duration::from_millis(10));
+    true
+}
+
 /// Adapter between schemer and SQLite.
+///
+/// Each migration is applied or reverted in its own transaction, begun with
+/// the configured `TransactionBehavior`. Processes sharing a database file can
+/// additionally be made to migrate one at a time with a run lock, see
+/// `RusqliteAdapter::set_lock_path`.
 pub struct RusqliteAdapter<'a, E> {
     conn: &'a mut Connection,
     migration_metadata_table: String,
+    transaction_behavior: TransactionBehavior,
+    lock_path: Option<PathBuf>,
+    lock_conn: Option<Connection>,
+    applied_by: Option<String>,
     _err: PhantomData<E>,
 }
 
@@ -115,25 +180,265 @@ impl<'a, E> RusqliteAdapter<'a, E> {
         RusqliteAdapter {
             conn,
             migration_metadata_table: table_name.unwrap_or_else(|| "_schemer".into()),
+            transaction_behavior: TransactionBehavior::Deferred,
+            lock_path: None,
+            lock_conn: None,
+            applied_by: None,
             _err: PhantomData,
         }
     }
 
+    /// Set who is recorded as applying migrations, such as the application's
+    /// version or the host name. Defaults to `None`.
+    pub fn set_applied_by(&mut self, applied_by: Option<String>) {
+        self.applied_by = applied_by;
+    }
+
+    /// Set the behavior with which the transaction of each migration is
+    /// begun. Defaults to `TransactionBehavior::Deferred`, which only takes
+    /// the database's write lock once a migration first writes.
+    /// `TransactionBehavior::Immediate` or `TransactionBehavior::Exclusive`
+    /// take it when the transaction begins, so that migrations of concurrent
+    /// connections do not interleave.
+    pub fn set_transaction_behavior(&mut self, behavior: TransactionBehavior) {
+        self.transaction_behavior = behavior;
+    }
+
+    /// Set the path of a lock file held while migrating up or down, or
+    /// disable the run lock if `None`, the default.
+    ///
+    /// Transactions alone do not prevent two processes from both finding a
+    /// migration unapplied and then both applying it. With a run lock, each
+    /// process waits for the lock before reading which migrations are applied,
+    /// so processes sharing a database migrate one at a time. All of them must
+    /// use the same lock path, for example the database's path with a `-lock`
+    /// suffix. The lock file is itself an empty SQLite database, and the lock
+    /// is released if the process holding it exits.
+    pub fn set_lock_path(&mut self, path: Option<PathBuf>) {
+        self.lock_path = path;
+    }
+
     /// Initialize the schemer metadata schema. This must be called before
     /// using `Migrator` with this adapter. This is safe to call multiple times.
+    ///
+    /// The version of the metadata schema is stored in a table named after
+    /// the metadata table with a `_version` suffix. Metadata tables created by
+    /// earlier versions of this crate are upgraded in place, keeping their
+    /// records. Tables of a later version are left unchanged.
     pub fn init(&self) -> Result<(), RusqliteError> {
-        self.conn.execute(
+        let table = &self.migration_metadata_table;
+        // Take the write lock up front, so that concurrent initializations
+        // see each other's changes.
+        let trans = Transaction::new_unchecked(self.conn, TransactionBehavior::Immediate)?;
+        trans.execute(
             &format!(
-                r#"
-                    CREATE TABLE IF NOT EXISTS {} (
-                        id blob PRIMARY KEY
-                    )
-                "#,
-                self.migration_metadata_table
+                "CREATE TABLE IF NOT EXISTS {}_version (version integer NOT NULL)",
+                table
             ),
             params![],
         )?;
-        Ok(())
+
+        let version: i32 = match trans
+            .query_row(
+                &format!("SELECT version FROM {}_version", table),
+                params![],
+                |row| row.get(0),
+            )
+            .optional()?
+        {
+            Some(version) => version,
+            None => {
+                // Tables predating versioning are version 1. Otherwise create
+                // the version 1 table, so that new tables are upgraded in the
+                // same way as existing ones.
+                if table_columns(&trans, table)?.is_empty() {
+                    trans.execute(
+                        &format!("CREATE TABLE {} (id blob PRIMARY KEY)", table),
+                        params![],
+                    )?;
+                }
+                trans.execute(
+                    &format!("INSERT INTO {}_version (version) VALUES (1)", table),
+                    params![],
+                )?;
+                1
+            }
+        };
+
+        if version < METADATA_VERSION {
+            for from in version..METADATA_VERSION {
+                upgrade_metadata(&trans, table, from)?;
+            }
+            trans.execute(
+                &format!("UPDATE {}_version SET version = ?1", table),
+                params![METADATA_VERSION],
+            )?;
+        }
+        trans.commit()
+    }
+}
+
+/// Version of the metadata schema created by `RusqliteAdapter::init`.
+const METADATA_VERSION: i32 = 3;
+
+/// Names of the columns of `table`, which are empty if it does not exist.
+fn table_columns(conn: &Connection, table: &str) -> Result<HashSet<String>, RusqliteError> {
+    let mut stmt = conn.prepare("SELECT name FROM pragma_table_info(?1)")?;
+    let rows = stmt.query_map(params![table], |row| row.get(0))?;
+    let mut columns = HashSet::new();
+    for row in rows {
+        columns.insert(row?);
+    }
+    Ok(columns)
+}
+
+/// Upgrade the metadata table `table` from version `from` to the next.
+fn upgrade_metadata(conn: &Connection, table: &str, from: i32) -> Result<(), RusqliteError> {
+    match from {
+        1 => {
+            // The table of schemer-rusqlite 0.2 holds only migration IDs, and
+            // SQLite adds a single column per `ALTER TABLE`.
+            for (column, column_type) in [
+                ("checksum", "text"),
+                ("description", "text"),
+                ("applied_at", "text"),
+                ("duration_us", "integer"),
+                ("applied_by", "text"),
+            ] {
+                conn.execute(
+                    &format!(
+                        "ALTER TABLE {} ADD COLUMN {} {}",
+                        table, column, column_type
+                    ),
+                    params![],
+                )?;
+            }
+        }
+        2 => {
+            conn.execute(
+                &format!(
+                    r#"
+                        CREATE TABLE IF NOT EXISTS {}_history (
+                            seq integer PRIMARY KEY,
+                            id blob NOT NULL,
+                            direction text NOT NULL,
+                            marked integer NOT NULL,
+                            occurred_at text NOT NULL,
+                            error text,
+                            performed_by text
+                        )
+                    "#,
+                    table
+                ),
+                params![],
+            )?;
+        }
+        _ => unreachable!("No upgrade from metadata version {}", from),
+    }
+    Ok(())
+}
+
+/// Record an attempt to apply, revert, or mark `migration` in the history
+/// table of the metadata table `table`.
+fn record_event<M: Migration + ?Sized>(
+    conn: &Connection,
+    table: &str,
+    migration: &M,
+    direction: MigrationDirection,
+    marked: bool,
+    error: Option<&str>,
+    performed_by: Option<&str>,
+) -> Result<(), RusqliteError> {
+    let uuid = migration.id();
+    let uuid_bytes = &uuid.as_bytes()[..];
+    let direction = match direction {
+        MigrationDirection::Up => "up",
+        MigrationDirection::Down => "down",
+    };
+    conn.execute(
+        &format!(
+            r#"
+                INSERT INTO {}_history (id, direction, marked, occurred_at, error, performed_by)
+                VALUES (?1, ?2, ?3, strftime('%Y-%m-%d %H:%M:%f', 'now'), ?4, ?5);
+            "#,
+            table
+        ),
+        params![uuid_bytes, direction, marked, error, performed_by],
+    )?;
+    Ok(())
+}
+
+/// Describe `error` along with its sources.
+fn error_text(error: &dyn Error) -> String {
+    let mut text = error.to_string();
+    let mut source = error.source();
+    while let Some(e) = source {
+        text.push_str(": ");
+        text.push_str(&e.to_string());
+        source = e.source();
+    }
+    text
+}
+
+/// Convert seconds since the Unix epoch, as selected from text timestamps via
+/// the Julian day, to a `SystemTime`.
+fn from_unix_secs(secs: f64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs_f64(secs)
+}
+
+impl<'a, E: From<RusqliteError> + Error> RusqliteAdapter<'a, E> {
+    /// Run `f` in a transaction and record its outcome in the history table.
+    /// Success is recorded in the same transaction, while failure is recorded
+    /// after the transaction is rolled back, logging a warning if this fails.
+    fn run_recorded<M, F>(
+        &mut self,
+        migration: &M,
+        direction: MigrationDirection,
+        marked: bool,
+        f: F,
+    ) -> Result<(), E>
+    where
+        M: Migration + ?Sized,
+        F: FnOnce(&Transaction<'_>, &str, Option<&str>) -> Result<(), E>,
+    {
+        let table = &self.migration_metadata_table;
+        let performed_by = self.applied_by.as_deref();
+        let result = (|| {
+            let trans = self
+                .conn
+                .transaction_with_behavior(self.transaction_behavior)?;
+            f(&trans, table, performed_by)?;
+            record_event(
+                &trans,
+                table,
+                migration,
+                direction,
+                marked,
+                None,
+                performed_by,
+            )?;
+            trans.commit().map_err(E::from)
+        })();
+        if let Err(e) = &result {
+            // The error of the attempt itself is returned, so one from
+            // recording it can only be logged.
+            if let Err(history_error) = record_event(
+                self.conn,
+                table,
+                migration,
+                direction,
+                marked,
+                Some(&error_text(e)),
+                performed_by,
+            ) {
+                warn!(
+                    "Failed to record failure of migration {} in history: {}",
+                    migration.id(),
+                    history_error
+                );
+            }
+        }
+        result
     }
 }
 
@@ -160,50 +465,174 @@ impl<'a, E: From<RusqliteError> + Sync + Send + Error + 'static> Adapter
     }
 
     fn apply_migration(&mut self, migration: &Self::MigrationType) -> Result<(), Self::Error> {
-        let trans = self.conn.transaction()?;
-        migration.up(&trans)?;
-        let uuid = migration.id();
-        let uuid_bytes = &uuid.as_bytes()[..];
-        trans.execute(
-            &format!(
-                "INSERT INTO {} (id) VALUES (?1);",
-                self.migration_metadata_table
-            ),
-            [&uuid_bytes],
-        )?;
-        trans.commit().map_err(|e| e.into())
+        self.run_recorded(
+            migration,
+            MigrationDirection::Up,
+            false,
+            |trans, table, applied_by| {
+                let start = Instant::now();
+                migration.up(trans)?;
+                record_migration(trans, table, migration, Some(start.elapsed()), applied_by)?;
+                Ok(())
+            },
+        )
     }
 
     fn revert_migration(&mut self, migration: &Self::MigrationType) -> Result<(), Self::Error> {
-        let trans = self.conn.transaction()?;
-        migration.down(&trans)?;
-        let uuid = migration.id();
-        let uuid_bytes = &uuid.as_bytes()[..];
-        trans.execute(
-            &format!(
-                "DELETE FROM {} WHERE id = ?1;",
-                self.migration_metadata_table
-            ),
-            [&uuid_bytes],
-        )?;
-        trans.commit().map_err(|e| e.into())
+        self.run_recorded(
+            migration,
+            MigrationDirection::Down,
+            false,
+            |trans, table, _| {
+                migration.down(trans)?;
+                unrecord_migration(trans, table, migration)?;
+                Ok(())
+            },
+        )
+    }
+
+    fn record_migration(&mut self, migration: &Self::MigrationType) -> Result<(), Self::Error> {
+        self.run_recorded(
+            migration,
+            MigrationDirection::Up,
+            true,
+            |trans, table, applied_by| {
+                record_migration(trans, table, migration, None, applied_by).map_err(|e| e.into())
+            },
+        )
+    }
+
+    fn unrecord_migration(&mut self, migration: &Self::MigrationType) -> Result<(), Self::Error> {
+        self.run_recorded(
+            migration,
+            MigrationDirection::Down,
+            true,
+            |trans, table, _| unrecord_migration(trans, table, migration).map_err(|e| e.into()),
+        )
+    }
+
+    fn applied_checksums(&mut self) -> Result<HashMap<Uuid, String>, Self::Error> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT id, checksum FROM {} WHERE checksum IS NOT NULL;",
+            self.migration_metadata_table
+        ))?;
+        let rows = stmt.query_map(params![], |row| {
+            Ok((row.get::<_, WrappedUuid>(0)?, row.get::<_, String>(1)?))
+        })?;
+        let mut checksums = HashMap::new();
+        for row in rows {
+            let (id, checksum) = row?;
+            checksums.insert(id.0, checksum);
+        }
+        Ok(checksums)
+    }
+
+    fn applied_records(&mut self) -> Result<Vec<AppliedMigration>, Self::Error> {
+        // Timestamps are stored as UTC text, which is converted to seconds
+        // since the Unix epoch via the Julian day.
+        let mut stmt = self.conn.prepare(&format!(
+            r#"
+                SELECT
+                    id,
+                    checksum,
+                    description,
+                    (julianday(applied_at) - 2440587.5) * 86400.0,
+                    duration_us,
+                    applied_by
+                FROM {}
+                ORDER BY applied_at, id;
+            "#,
+            self.migration_metadata_table
+        ))?;
+        let rows = stmt.query_map(params![], |row| {
+            Ok(AppliedMigration {
+                id: row.get::<_, WrappedUuid>(0)?.0,
+                checksum: row.get(1)?,
+                description: row.get(2)?,
+                applied_at: row.get::<_, Option<f64>>(3)?.map(from_unix_secs),
+                duration: row
+                    .get::<_, Option<i64>>(4)?
+                    .map(|us| Duration::from_micros(us as u64)),
+                applied_by: row.get(5)?,
+            })
+        })?;
+        let mut records = vec![];
+        for row in rows {
+            records.push(row?);
+        }
+        Ok(records)
+    }
+
+    fn history(&mut self) -> Result<Vec<MigrationEvent>, Self::Error> {
+        let mut stmt = self.conn.prepare(&format!(
+            r#"
+                SELECT
+                    id,
+                    direction,
+                    marked,
+                    (julianday(occurred_at) - 2440587.5) * 86400.0,
+                    error,
+                    performed_by
+                FROM {}_history
+                ORDER BY seq;
+            "#,
+            self.migration_metadata_table
+        ))?;
+        let rows = stmt.query_map(params![], |row| {
+            Ok(MigrationEvent {
+                id: row.get::<_, WrappedUuid>(0)?.0,
+                direction: match row.get_ref(1)?.as_str()? {
+                    "down" => MigrationDirection::Down,
+                    _ => MigrationDirection::Up,
+                },
+                marked: row.get(2)?,
+                occurred_at: from_unix_secs(row.get(3)?),
+                error: row.get(4)?,
+                performed_by: row.get(5)?,
+            })
+        })?;
+        let mut events = vec![];
+        for row in rows {
+            events.push(row?);
+        }
+        Ok(events)
+    }
+
+    fn lock(&mut self) -> Result<(), Self::Error> {
+        if let Some(path) = &self.lock_path {
+            let lock_conn = Connection::open(path)?;
+            lock_conn.busy_handler(Some(wait_for_lock))?;
+            lock_conn.execute_batch("BEGIN EXCLUSIVE;")?;
+            self.lock_conn = Some(lock_conn);
+        }
+        Ok(())
+    }
+
+    fn unlock(&mut self) -> Result<(), Self::Error> {
+        if let Some(lock_conn) = self.lock_conn.take() {
+            lock_conn.execute_batch("COMMIT;")?;
+        }
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::path::Path;
+    use std::sync::{mpsc, Arc, Barrier};
+
     use super::*;
     use rusqlite::Error as RusqliteError;
-    use schemer::test_schemer_adapter;
     use schemer::testing::*;
+    use schemer::{migration, test_schemer_adapter, Migrator};
 
     impl RusqliteMigration for TestMigration {
         type Error = RusqliteError;
     }
 
     impl<'a> TestAdapter for RusqliteAdapter<'a, RusqliteError> {
-        fn mock(id: Uuid, dependencies: HashSet<Uuid>) -> Box<Self::MigrationType> {
-            Box::new(TestMigration::new(id, dependencies))
+        fn mock_from(migration: TestMigration) -> Box<Self::MigrationType> {
+            Box::new(migration)
         }
     }
 
@@ -220,4 +649,218 @@ mod tests {
     test_schemer_adapter!(
         let mut conn = build_test_connection(),
         build_test_adapter(&mut conn));
+
+    struct CreateTableMigration;
+    migration!(
+        CreateTableMigration,
+        "bc960dc8-0e4a-4182-a62a-8e776d1e2b30",
+        [],
+        "Create a table, failing if it exists."
+    );
+
+    impl RusqliteMigration for CreateTableMigration {
+        type Error = RusqliteError;
+
+        fn up(&self, transaction: &Transaction<'_>) -> Result<(), Self::Error> {
+            // Widen the window in which concurrent migrators could race.
+            thread::sleep(Duration::from_millis(50));
+            transaction.execute_batch("CREATE TABLE created (id integer PRIMARY KEY);")
+        }
+    }
+
+    struct FailingMigration;
+    migration!(
+        FailingMigration,
+        "4885e8ab-dafa-4d76-a565-2dee8b04ef60",
+        ["bc960dc8-0e4a-4182-a62a-8e776d1e2b30"],
+        "A migration that fails to apply."
+    );
+
+    impl RusqliteMigration for FailingMigration {
+        type Error = RusqliteError;
+
+        fn up(&self, transaction: &Transaction<'_>) -> Result<(), Self::Error> {
+            transaction.execute_batch("SELECT * FROM nonexistent;")
+        }
+    }
+
+    fn build_file_adapter<'a>(
+        conn: &'a mut Connection,
+        dir: &Path,
+    ) -> RusqliteAdapter<'a, RusqliteError> {
+        conn.busy_timeout(Duration::from_secs(10)).unwrap();
+        let mut adapter = RusqliteAdapter::new(conn, None);
+        adapter.set_transaction_behavior(TransactionBehavior::Immediate);
+        adapter.set_lock_path(Some(dir.join("test.db-lock")));
+        adapter
+    }
+
+    #[test]
+    fn test_upgrade_metadata() {
+        let mut conn = build_test_connection();
+        let id = Uuid::parse_str("4885e8ab-dafa-4d76-a565-2dee8b04ef60").unwrap();
+        conn.execute_batch("CREATE TABLE _schemer (id blob PRIMARY KEY);")
+            .unwrap();
+        conn.execute(
+            "INSERT INTO _schemer (id) VALUES (?1)",
+            params![&id.as_bytes()[..]],
+        )
+        .unwrap();
+
+        let mut adapter = build_test_adapter(&mut conn);
+        adapter.init().unwrap();
+        assert_eq!(
+            adapter.applied_records().unwrap(),
+            vec![AppliedMigration::new(id)]
+        );
+        adapter.apply_migration(&CreateTableMigration).unwrap();
+        let records = adapter.applied_records().unwrap();
+        assert_eq!(records.len(), 2);
+        assert!(records[1].applied_at.is_some());
+
+        let version: i32 = conn
+            .query_row("SELECT version FROM _schemer_version", params![], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(version, METADATA_VERSION);
+    }
+
+    #[test]
+    fn test_history() {
+        let mut conn = build_test_connection();
+        let mut adapter = build_test_adapter(&mut conn);
+        adapter.set_applied_by(Some("test".into()));
+        let id = CreateTableMigration.id();
+        let mut migrator = Migrator::new(adapter);
+        migrator
+            .register_multiple(vec![
+                Box::new(CreateTableMigration),
+                Box::new(FailingMigration),
+            ])
+            .unwrap();
+
+        assert!(migrator.up(None).is_err());
+        migrator.down(None).unwrap();
+        migrator.mark_applied(&[id]).unwrap();
+
+        let history = migrator.history().unwrap();
+        let events: Vec<_> = history
+            .iter()
+            .map(|event| {
+                (
+                    event.id,
+                    event.direction,
+                    event.marked,
+                    event.error.is_some(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                (id, MigrationDirection::Up, false, false),
+                (FailingMigration.id(), MigrationDirection::Up, false, true),
+                (id, MigrationDirection::Down, false, false),
+                (id, MigrationDirection::Up, true, false),
+            ]
+        );
+        assert!(history[1].error.as_ref().unwrap().contains("nonexistent"));
+        assert!(history
+            .iter()
+            .all(|event| event.performed_by.as_deref() == Some("test")));
+    }
+
+    #[test]
+    fn test_history_failure() {
+        let mut conn = build_test_connection();
+        let mut adapter = build_test_adapter(&mut conn);
+        adapter
+            .conn
+            .execute_batch("DROP TABLE _schemer_history;")
+            .unwrap();
+
+        let error = adapter.apply_migration(&FailingMigration).unwrap_err();
+        assert!(error.to_string().contains("nonexistent"));
+        assert!(adapter.history().is_err());
+        assert!(adapter.applied_migrations().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_applied_record_metadata() {
+        let mut conn = build_test_connection();
+        let mut adapter = build_test_adapter(&mut conn);
+        adapter.set_applied_by(Some("test".into()));
+        let before = SystemTime::now() - Duration::from_secs(1);
+
+        adapter.apply_migration(&CreateTableMigration).unwrap();
+        let records = adapter.applied_records().unwrap();
+        assert_eq!(records.len(), 1);
+        let record = &records[0];
+        assert_eq!(record.id, CreateTableMigration.id());
+        assert_eq!(
+            record.description.as_deref(),
+            Some("Create a table, failing if it exists.")
+        );
+        assert!(record.applied_at.unwrap() > before);
+        assert!(record.applied_at.unwrap() < SystemTime::now() + Duration::from_secs(1));
+        assert!(record.duration.unwrap() >= Duration::from_millis(50));
+        assert_eq!(record.applied_by.as_deref(), Some("test"));
+    }
+
+    #[test]
+    fn test_run_lock() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut conn = Connection::open(dir.path().join("test.db")).unwrap();
+        let mut adapter = build_file_adapter(&mut conn, dir.path());
+        adapter.lock().unwrap();
+
+        let (sender, receiver) = mpsc::channel();
+        let other_dir = dir.path().to_owned();
+        let other = thread::spawn(move || {
+            let mut other_conn = Connection::open(other_dir.join("test.db")).unwrap();
+            let mut other_adapter = build_file_adapter(&mut other_conn, &other_dir);
+            other_adapter.lock().unwrap();
+            sender.send(()).unwrap();
+            other_adapter.unlock().unwrap();
+        });
+
+        assert_eq!(
+            receiver.recv_timeout(Duration::from_millis(200)),
+            Err(mpsc::RecvTimeoutError::Timeout)
+        );
+        adapter.unlock().unwrap();
+        receiver.recv_timeout(Duration::from_secs(10)).unwrap();
+        other.join().unwrap();
+    }
+
+    #[test]
+    fn test_concurrent_migrators() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut conn = Connection::open(dir.path().join("test.db")).unwrap();
+        build_file_adapter(&mut conn, dir.path()).init().unwrap();
+
+        let barrier = Arc::new(Barrier::new(4));
+        let migrators: Vec<_> = (0..4)
+            .map(|_| {
+                let dir = dir.path().to_owned();
+                let barrier = barrier.clone();
+                thread::spawn(move || {
+                    let mut conn = Connection::open(dir.join("test.db")).unwrap();
+                    let mut migrator = Migrator::new(build_file_adapter(&mut conn, &dir));
+                    migrator.register(Box::new(CreateTableMigration)).unwrap();
+                    barrier.wait();
+                    migrator.up(None).unwrap().executed.len()
+                })
+            })
+            .collect();
+        let executed: usize = migrators.into_iter().map(|m| m.join().unwrap()).sum();
+        assert_eq!(executed, 1);
+
+        let mut adapter = build_file_adapter(&mut conn, dir.path());
+        assert_eq!(
+            adapter.applied_migrations().unwrap(),
+            vec![CreateTableMigration.id()].into_iter().collect()
+        );
+    }
 }
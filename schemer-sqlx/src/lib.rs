@@ -0,0 +1,376 @@
+//! An adapter enabling use of the schemer schema migration library with any
+//! database supported by sqlx: PostgreSQL, SQLite and MySQL.
+//!
+//! Backends are enabled with the `postgres`, `sqlite` and `mysql` features,
+//! all of which are enabled by default. As with sqlx itself, an async runtime
+//! must be selected through sqlx's own `runtime-*` features.
+//!
+//! A migration implements `SqlxMigration` for each backend it targets, or
+//! generically for all of them if its SQL is portable.
+//!
+//! # Examples:
+//!
+//! ```rust
+//! extern crate schemer;
+//! extern crate schemer_sqlx;
+//! extern crate sqlx;
+//! extern crate tokio;
+//! extern crate uuid;
+//!
+//! use std::collections::HashSet;
+//!
+//! use schemer::{async_trait, migration, AsyncMigrator, Migration};
+//! use schemer_sqlx::{SqlxAdapter, SqlxAdapterError, SqlxMigration};
+//! use sqlx::{Connection, Sqlite, SqliteConnection, Transaction};
+//! use uuid::Uuid;
+//!
+//! struct MyExampleMigration;
+//! migration!(
+//!     MyExampleMigration,
+//!     "4885e8ab-dafa-4d76-a565-2dee8b04ef60",
+//!     [],
+//!     "An example migration without dependencies.");
+//!
+//! #[async_trait]
+//! impl SqlxMigration<Sqlite> for MyExampleMigration {
+//!     async fn up(&self, transaction: &mut Transaction<'_, Sqlite>) -> Result<(), SqlxAdapterError> {
+//!         sqlx::query("CREATE TABLE my_example (id integer PRIMARY KEY);")
+//!             .execute(&mut **transaction)
+//!             .await?;
+//!         Ok(())
+//!     }
+//!
+//!     async fn down(&self, transaction: &mut Transaction<'_, Sqlite>) -> Result<(), SqlxAdapterError> {
+//!         sqlx::query("DROP TABLE my_example;")
+//!             .execute(&mut **transaction)
+//!             .await?;
+//!         Ok(())
+//!     }
+//! }
+//!
+//! #[tokio::main(flavor = "current_thread")]
+//! async fn main() {
+//!     let mut conn = SqliteConnection::connect("sqlite::memory:").await.unwrap();
+//!     let mut adapter = SqlxAdapter::<Sqlite>::new(&mut conn, None);
+//!     adapter.init().await.unwrap();
+//!
+//!     let mut migrator = AsyncMigrator::new(adapter);
+//!
+//!     let migration = Box::new(MyExampleMigration {});
+//!     migrator.register(migration);
+//!     migrator.up(None).await;
+//! }
+//! ```
+#![warn(clippy::all)]
+#![forbid(unsafe_code)]
+
+use schemer::{async_trait, Migration};
+use sqlx::{Database, Error as SqlxError, Transaction};
+
+/// sqlx-specific trait for schema migrations on the database `DB`.
+#[async_trait]
+pub trait SqlxMigration<DB: Database>: Migration + Send + Sync {
+    /// Apply a migration to the database using a transaction.
+    async fn up(&self, _transaction: &mut Transaction<'_, DB>) -> Result<(), SqlxError> {
+        Ok(())
+    }
+
+    /// Revert a migration to the database using a transaction.
+    /// Does nothing by default; see [`Migration::reversible`].
+    async fn down(&self, _transaction: &mut Transaction<'_, DB>) -> Result<(), SqlxError> {
+        Ok(())
+    }
+}
+
+pub type SqlxAdapterError = SqlxError;
+
+/// Adapter between schemer and a database `DB` supported by sqlx.
+///
+/// `AsyncAdapter` is implemented for each backend enabled by this crate's
+/// features. The metadata table stores only migration IDs and checksums:
+/// unlike `schemer-postgres`, `schemer-tokio-postgres` and `schemer-rusqlite`,
+/// this adapter keeps no schema version or history tables. Do not use it on
+/// the same database as those adapters, as migrations it runs would be missing
+/// from their history.
+pub struct SqlxAdapter<'a, DB: Database> {
+    conn: &'a mut DB::Connection,
+    migration_metadata_table: String,
+}
+
+impl<'a, DB: Database> SqlxAdapter<'a, DB> {
+    /// Construct a sqlx schemer adapter.
+    ///
+    /// `table_name` specifies the name of the table that schemer will use
+    /// for storing metadata about applied migrations. If `None`, a default
+    /// will be used.
+    pub fn new(conn: &'a mut DB::Connection, table_name: Option<String>) -> SqlxAdapter<'a, DB> {
+        SqlxAdapter {
+            conn,
+            migration_metadata_table: table_name.unwrap_or_else(|| "_schemer".into()),
+        }
+    }
+}
+
+/// Implement `init` and `AsyncAdapter` for a sqlx backend, given the column
+/// type for migration IDs and the bind parameter placeholders of its SQL
+/// dialect.
+macro_rules! impl_sqlx_adapter {
+    ($db:ty, $id_type:literal, $param1:literal, $param2:literal) => {
+        impl<'a> SqlxAdapter<'a, $db> {
+            /// Initialize the schemer metadata schema. This must be called
+            /// before using `AsyncMigrator` with this adapter. This is safe to
+            /// call multiple times.
+            pub async fn init(&mut self) -> Result<(), SqlxError> {
+                let sql = format!(
+                    r#"
+                        CREATE TABLE IF NOT EXISTS {} (
+                            id {} PRIMARY KEY,
+                            checksum text
+                        )
+                    "#,
+                    self.migration_metadata_table, $id_type
+                );
+                sqlx::query(&sql).execute(&mut *self.conn).await?;
+                Ok(())
+            }
+        }
+
+        #[async_trait]
+        impl<'a> schemer::AsyncAdapter for SqlxAdapter<'a, $db> {
+            type MigrationType = dyn SqlxMigration<$db>;
+
+            type Error = SqlxAdapterError;
+
+            async fn applied_migrations(
+                &mut self,
+            ) -> Result<std::collections::HashSet<uuid::Uuid>, Self::Error> {
+                let sql = format!("SELECT id FROM {};", self.migration_metadata_table);
+                let ids = sqlx::query_scalar::<_, uuid::Uuid>(&sql)
+                    .fetch_all(&mut *self.conn)
+                    .await?;
+                Ok(ids.into_iter().collect())
+            }
+
+            async fn apply_migration(
+                &mut self,
+                migration: &Self::MigrationType,
+            ) -> Result<(), Self::Error> {
+                let mut trans = sqlx::Connection::begin(&mut *self.conn).await?;
+                migration.up(&mut trans).await?;
+                let sql = format!(
+                    "INSERT INTO {} (id, checksum) VALUES ({}, {});",
+                    self.migration_metadata_table, $param1, $param2
+                );
+                sqlx::query(&sql)
+                    .bind(migration.id())
+                    .bind(migration.checksum())
+                    .execute(&mut *trans)
+                    .await?;
+                trans.commit().await
+            }
+
+            async fn revert_migration(
+                &mut self,
+                migration: &Self::MigrationType,
+            ) -> Result<(), Self::Error> {
+                let mut trans = sqlx::Connection::begin(&mut *self.conn).await?;
+                migration.down(&mut trans).await?;
+                let sql = format!(
+                    "DELETE FROM {} WHERE id = {};",
+                    self.migration_metadata_table, $param1
+                );
+                sqlx::query(&sql)
+                    .bind(migration.id())
+                    .execute(&mut *trans)
+                    .await?;
+                trans.commit().await
+            }
+
+            async fn record_migration(
+                &mut self,
+                migration: &Self::MigrationType,
+            ) -> Result<(), Self::Error> {
+                let sql = format!(
+                    "INSERT INTO {} (id, checksum) VALUES ({}, {});",
+                    self.migration_metadata_table, $param1, $param2
+                );
+                sqlx::query(&sql)
+                    .bind(migration.id())
+                    .bind(migration.checksum())
+                    .execute(&mut *self.conn)
+                    .await?;
+                Ok(())
+            }
+
+            async fn unrecord_migration(
+                &mut self,
+                migration: &Self::MigrationType,
+            ) -> Result<(), Self::Error> {
+                let sql = format!(
+                    "DELETE FROM {} WHERE id = {};",
+                    self.migration_metadata_table, $param1
+                );
+                sqlx::query(&sql)
+                    .bind(migration.id())
+                    .execute(&mut *self.conn)
+                    .await?;
+                Ok(())
+            }
+
+            async fn applied_checksums(
+                &mut self,
+            ) -> Result<std::collections::HashMap<uuid::Uuid, String>, Self::Error> {
+                let sql = format!(
+                    "SELECT id, checksum FROM {} WHERE checksum IS NOT NULL;",
+                    self.migration_metadata_table
+                );
+                let rows = sqlx::query_as::<_, (uuid::Uuid, String)>(&sql)
+                    .fetch_all(&mut *self.conn)
+                    .await?;
+                Ok(rows.into_iter().collect())
+            }
+        }
+    };
+}
+
+#[cfg(feature = "postgres")]
+impl_sqlx_adapter!(sqlx::Postgres, "uuid", "$1", "$2");
+
+#[cfg(feature = "sqlite")]
+impl_sqlx_adapter!(sqlx::Sqlite, "blob", "?1", "?2");
+
+#[cfg(feature = "mysql")]
+impl_sqlx_adapter!(sqlx::MySql, "binary(16)", "?", "?");
+
+#[cfg(test)]
+mod tests {
+    use std::future::Future;
+
+    use super::*;
+    use schemer::testing::*;
+    use schemer::{test_schemer_async_adapter, AsyncAdapter};
+
+    impl<DB: Database> SqlxMigration<DB> for TestMigration {}
+
+    impl<'a, DB: Database> AsyncTestAdapter for SqlxAdapter<'a, DB>
+    where
+        SqlxAdapter<'a, DB>: AsyncAdapter<MigrationType = dyn SqlxMigration<DB>>,
+    {
+        fn mock_from(migration: TestMigration) -> Box<Self::MigrationType> {
+            Box::new(migration)
+        }
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    #[cfg(feature = "postgres")]
+    mod postgres {
+        use super::*;
+        use sqlx::{Connection, PgConnection, Postgres};
+
+        async fn build_test_connection() -> PgConnection {
+            let mut conn = PgConnection::connect("postgresql://postgres@localhost")
+                .await
+                .unwrap();
+            sqlx::query("SET search_path = pg_temp")
+                .execute(&mut conn)
+                .await
+                .unwrap();
+            conn
+        }
+
+        async fn build_test_adapter(conn: &mut PgConnection) -> SqlxAdapter<'_, Postgres> {
+            let mut adapter = SqlxAdapter::<Postgres>::new(conn, None);
+            adapter.init().await.unwrap();
+            adapter
+        }
+
+        test_schemer_async_adapter!(
+            block_on,
+            let mut conn = build_test_connection().await,
+            build_test_adapter(&mut conn).await);
+    }
+
+    #[cfg(feature = "sqlite")]
+    mod sqlite {
+        use super::*;
+        use sqlx::{Connection, Sqlite, SqliteConnection};
+
+        async fn build_test_connection() -> SqliteConnection {
+            SqliteConnection::connect("sqlite::memory:").await.unwrap()
+        }
+
+        async fn build_test_adapter(conn: &mut SqliteConnection) -> SqlxAdapter<'_, Sqlite> {
+            let mut adapter = SqlxAdapter::<Sqlite>::new(conn, None);
+            adapter.init().await.unwrap();
+            adapter
+        }
+
+        test_schemer_async_adapter!(
+            block_on,
+            let mut conn = build_test_connection().await,
+            build_test_adapter(&mut conn).await);
+    }
+
+    /// MySQL tests run against the database at `SCHEMER_MYSQL_URL`. They are
+    /// ignored by default, so run them with `--ignored`.
+    #[cfg(feature = "mysql")]
+    mod mysql {
+        use super::*;
+        use sqlx::{Connection, MySql, MySqlConnection};
+
+        /// Connection to the test database, dropping the metadata table of
+        /// the test using it when dropped.
+        struct TestDatabase {
+            conn: MySqlConnection,
+            url: String,
+            table_name: String,
+        }
+
+        impl TestDatabase {
+            async fn connect() -> TestDatabase {
+                let url = std::env::var("SCHEMER_MYSQL_URL")
+                    .expect("SCHEMER_MYSQL_URL must be set to run MySQL tests");
+                TestDatabase {
+                    conn: MySqlConnection::connect(&url).await.unwrap(),
+                    url,
+                    table_name: unique_table_name(),
+                }
+            }
+        }
+
+        impl Drop for TestDatabase {
+            fn drop(&mut self) {
+                // Dropping can not await, so the table is dropped on a new
+                // connection in a runtime of its own.
+                let url = self.url.clone();
+                let sql = format!("DROP TABLE IF EXISTS {}", self.table_name);
+                let _ = std::thread::spawn(move || {
+                    block_on(async {
+                        let mut conn = MySqlConnection::connect(&url).await?;
+                        sqlx::query(&sql).execute(&mut conn).await
+                    })
+                })
+                .join();
+            }
+        }
+
+        async fn build_test_adapter(db: &mut TestDatabase) -> SqlxAdapter<'_, MySql> {
+            let mut adapter = SqlxAdapter::<MySql>::new(&mut db.conn, Some(db.table_name.clone()));
+            adapter.init().await.unwrap();
+            adapter
+        }
+
+        test_schemer_async_adapter!(
+            block_on,
+            #[ignore = "requires a MySQL database at SCHEMER_MYSQL_URL"]
+            let mut db = TestDatabase::connect().await,
+            build_test_adapter(&mut db).await);
+    }
+}
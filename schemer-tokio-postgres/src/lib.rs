@@ -0,0 +1,806 @@
+//! An adapter enabling use of the schemer schema migration library with
+//! PostgreSQL through the asynchronous tokio-postgres driver.
+//!
+//! # Examples:
+//!
+//! ```rust
+//! extern crate schemer;
+//! extern crate schemer_tokio_postgres;
+//! extern crate tokio;
+//! extern crate tokio_postgres;
+//! extern crate uuid;
+//!
+//! use std::collections::HashSet;
+//!
+//! use schemer::{async_trait, migration, AsyncMigrator, Migration};
+//! use schemer_tokio_postgres::{
+//!     TokioPostgresAdapter, TokioPostgresAdapterError, TokioPostgresMigration,
+//! };
+//! use tokio_postgres::{NoTls, Transaction};
+//! use uuid::Uuid;
+//!
+//! struct MyExampleMigration;
+//! migration!(
+//!     MyExampleMigration,
+//!     "4885e8ab-dafa-4d76-a565-2dee8b04ef60",
+//!     [],
+//!     "An example migration without dependencies.");
+//!
+//! #[async_trait]
+//! impl TokioPostgresMigration for MyExampleMigration {
+//!     async fn up(&self, transaction: &mut Transaction<'_>) -> Result<(), TokioPostgresAdapterError> {
+//!         transaction.execute("CREATE TABLE my_example (id integer PRIMARY KEY);", &[]).await?;
+//!         Ok(())
+//!     }
+//!
+//!     async fn down(&self, transaction: &mut Transaction<'_>) -> Result<(), TokioPostgresAdapterError> {
+//!         transaction.execute("DROP TABLE my_example;", &[]).await?;
+//!         Ok(())
+//!     }
+//! }
+//!
+//! #[tokio::main(flavor = "current_thread")]
+//! async fn main() {
+//!     let (mut client, connection) = tokio_postgres::connect(
+//!         "postgresql://postgres@localhost",
+//!         NoTls).await.unwrap();
+//!     tokio::spawn(connection);
+//!     client.execute("SET search_path = pg_temp", &[]).await.unwrap();
+//!     let mut adapter = TokioPostgresAdapter::new(&mut client, None);
+//!     adapter.init().await.unwrap();
+//!
+//!     let mut migrator = AsyncMigrator::new(adapter);
+//!
+//!     let migration = Box::new(MyExampleMigration {});
+//!     migrator.register(migration);
+//!     migrator.up(None).await;
+//! }
+//! ```
+#![warn(clippy::all)]
+#![forbid(unsafe_code)]
+
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant, SystemTime};
+
+use log::warn;
+use schemer::{
+    async_trait, AppliedMigration, AsyncAdapter, Migration, MigrationDirection, MigrationEvent,
+};
+use tokio_postgres::{Client, Error as PostgresError, GenericClient, Transaction};
+use uuid::Uuid;
+
+/// PostgreSQL-specific trait for asynchronous schema migrations.
+#[async_trait]
+pub trait TokioPostgresMigration: Migration + Send + Sync {
+    /// Apply a migration to the database using a transaction.
+    async fn up(&self, _transaction: &mut Transaction<'_>) -> Result<(), PostgresError> {
+        Ok(())
+    }
+
+    /// Revert a migration to the database using a transaction.
+    /// Does nothing by default; see [`Migration::reversible`].
+    async fn down(&self, _transaction: &mut Transaction<'_>) -> Result<(), PostgresError> {
+        Ok(())
+    }
+}
+
+pub type TokioPostgresAdapterError = PostgresError;
+
+/// Key of the advisory lock held while migrating, unless configured otherwise
+/// with `TokioPostgresAdapter::set_advisory_lock`. This is the same as the
+/// default of `schemer-postgres`.
+pub const DEFAULT_ADVISORY_LOCK: i64 = 0x0073_6368_656d_6572;
+
+/// Asynchronous adapter between schemer and PostgreSQL.
+///
+/// The adapter borrows a `tokio_postgres::Client`, so it can also be used with
+/// pooled connections that dereference to one, such as those of
+/// `deadpool-postgres`.
+///
+/// While migrating up or down, the adapter holds a session-level advisory
+/// lock, so that processes migrating the same database concurrently wait for
+/// each other and each migration is applied once.
+///
+/// The metadata tables, their default name and the default lock key are the
+/// same as those of `schemer-postgres`, so both adapters can migrate the same
+/// database.
+pub struct TokioPostgresAdapter<'a> {
+    conn: &'a mut Client,
+    migration_metadata_table: String,
+    advisory_lock: Option<i64>,
+    applied_by: Option<String>,
+}
+
+impl<'a> TokioPostgresAdapter<'a> {
+    /// Construct an asynchronous PostgreSQL schemer adapter.
+    ///
+    /// `table_name` specifies the name of the table that schemer will use
+    /// for storing metadata about applied migrations. If `None`, a default
+    /// will be used.
+    pub fn new(conn: &'a mut Client, table_name: Option<String>) -> TokioPostgresAdapter<'a> {
+        TokioPostgresAdapter {
+            conn,
+            migration_metadata_table: table_name.unwrap_or_else(|| "_schemer".into()),
+            advisory_lock: Some(DEFAULT_ADVISORY_LOCK),
+            applied_by: None,
+        }
+    }
+
+    /// Set who is recorded as applying migrations, such as the application's
+    /// version or the host name. Defaults to `None`.
+    pub fn set_applied_by(&mut self, applied_by: Option<String>) {
+        self.applied_by = applied_by;
+    }
+
+    /// Set the key of the advisory lock held while migrating, or disable
+    /// locking if `None`. Defaults to `DEFAULT_ADVISORY_LOCK`.
+    pub fn set_advisory_lock(&mut self, key: Option<i64>) {
+        self.advisory_lock = key;
+    }
+
+    /// Initialize the schemer metadata schema. This must be called before
+    /// using `AsyncMigrator` with this adapter. This is safe to call multiple
+    /// times.
+    ///
+    /// As with `schemer-postgres`, the version of the metadata schema is
+    /// stored in a table named after the metadata table with a `_version`
+    /// suffix, and tables of earlier versions are upgraded in place.
+    pub async fn init(&mut self) -> Result<(), PostgresError> {
+        let table = &self.migration_metadata_table;
+        let trans = self.conn.transaction().await?;
+        // Concurrent initializations would otherwise race to create tables.
+        if let Some(key) = self.advisory_lock {
+            trans
+                .execute("SELECT pg_advisory_xact_lock($1)", &[&key])
+                .await?;
+        }
+        trans
+            .execute(
+                format!(
+                    "CREATE TABLE IF NOT EXISTS {}_version (version integer NOT NULL)",
+                    table
+                )
+                .as_str(),
+                &[],
+            )
+            .await?;
+
+        let version: i32 = match trans
+            .query_opt(
+                format!("SELECT version FROM {}_version", table).as_str(),
+                &[],
+            )
+            .await?
+        {
+            Some(row) => row.get(0),
+            None => {
+                let exists: bool = trans
+                    .query_one("SELECT to_regclass($1) IS NOT NULL", &[table])
+                    .await?
+                    .get(0);
+                // A table without a version is one of `schemer-postgres` 0.2.
+                // New tables start from the same schema to share its upgrades.
+                if !exists {
+                    trans
+                        .execute(
+                            format!(
+                                r#"
+                                    CREATE TABLE {} (
+                                        id uuid PRIMARY KEY
+                                    ) WITH (
+                                        OIDS=FALSE
+                                    )
+                                "#,
+                                table
+                            )
+                            .as_str(),
+                            &[],
+                        )
+                        .await?;
+                }
+                trans
+                    .execute(
+                        format!("INSERT INTO {}_version (version) VALUES (1)", table).as_str(),
+                        &[],
+                    )
+                    .await?;
+                1
+            }
+        };
+
+        if version < METADATA_VERSION {
+            for from in version..METADATA_VERSION {
+                trans.batch_execute(&metadata_upgrade(table, from)).await?;
+            }
+            trans
+                .execute(
+                    format!("UPDATE {}_version SET version = $1", table).as_str(),
+                    &[&METADATA_VERSION],
+                )
+                .await?;
+        }
+        trans.commit().await
+    }
+}
+
+/// Version of the metadata schema created by `TokioPostgresAdapter::init`,
+/// which must match that of `schemer-postgres`.
+const METADATA_VERSION: i32 = 3;
+
+/// SQL upgrading the metadata table `table` from version `from` to the next.
+fn metadata_upgrade(table: &str, from: i32) -> String {
+    match from {
+        1 => format!(
+            r#"
+                ALTER TABLE {}
+                    ADD COLUMN checksum text,
+                    ADD COLUMN description text,
+                    ADD COLUMN applied_at timestamptz,
+                    ADD COLUMN duration_us bigint,
+                    ADD COLUMN applied_by text;
+            "#,
+            table
+        ),
+        2 => format!(
+            r#"
+                CREATE TABLE IF NOT EXISTS {}_history (
+                    seq bigserial PRIMARY KEY,
+                    id uuid NOT NULL,
+                    direction text NOT NULL,
+                    marked boolean NOT NULL,
+                    occurred_at timestamptz NOT NULL,
+                    error text,
+                    performed_by text
+                );
+            "#,
+            table
+        ),
+        _ => unreachable!("No upgrade from metadata version {}", from),
+    }
+}
+
+async fn record_migration<C: GenericClient + Sync>(
+    client: &C,
+    table: &str,
+    migration: &dyn TokioPostgresMigration,
+    duration: Option<Duration>,
+    applied_by: Option<&str>,
+) -> Result<(), PostgresError> {
+    let duration_us = duration.map(|d| d.as_micros() as i64);
+    client
+        .execute(
+            format!(
+                r#"
+                    INSERT INTO {} (id, checksum, description, applied_at, duration_us, applied_by)
+                    VALUES ($1::uuid, $2, $3, now(), $4, $5);
+                "#,
+                table
+            )
+            .as_str(),
+            &[
+                &migration.id(),
+                &migration.checksum(),
+                &migration.description(),
+                &duration_us,
+                &applied_by,
+            ],
+        )
+        .await?;
+    Ok(())
+}
+
+async fn unrecord_migration<C: GenericClient + Sync>(
+    client: &C,
+    table: &str,
+    migration: &dyn TokioPostgresMigration,
+) -> Result<(), PostgresError> {
+    client
+        .execute(
+            format!("DELETE FROM {} WHERE id = $1::uuid;", table).as_str(),
+            &[&migration.id()],
+        )
+        .await?;
+    Ok(())
+}
+
+/// Record an attempt to apply, revert, or mark `migration` in the history
+/// table of the metadata table `table`.
+async fn record_event<C: GenericClient + Sync>(
+    client: &C,
+    table: &str,
+    migration: &dyn TokioPostgresMigration,
+    direction: MigrationDirection,
+    marked: bool,
+    error: Option<&str>,
+    performed_by: Option<&str>,
+) -> Result<(), PostgresError> {
+    let direction = match direction {
+        MigrationDirection::Up => "up",
+        MigrationDirection::Down => "down",
+    };
+    client
+        .execute(
+            format!(
+                r#"
+                    INSERT INTO {}_history (id, direction, marked, occurred_at, error, performed_by)
+                    VALUES ($1::uuid, $2, $3, now(), $4, $5);
+                "#,
+                table
+            )
+            .as_str(),
+            &[&migration.id(), &direction, &marked, &error, &performed_by],
+        )
+        .await?;
+    Ok(())
+}
+
+/// Describe `error` along with its sources, which for database errors hold
+/// the message from the server.
+fn error_text(error: &dyn std::error::Error) -> String {
+    let mut text = error.to_string();
+    let mut source = error.source();
+    while let Some(e) = source {
+        text.push_str(": ");
+        text.push_str(&e.to_string());
+        source = e.source();
+    }
+    text
+}
+
+/// Apply, revert, or mark `migration` in a transaction, depending on
+/// `direction` and whether it is only `marked`, and record it in the history
+/// table in the same transaction.
+async fn run_in_transaction(
+    conn: &mut Client,
+    table: &str,
+    migration: &dyn TokioPostgresMigration,
+    direction: MigrationDirection,
+    marked: bool,
+    performed_by: Option<&str>,
+) -> Result<(), PostgresError> {
+    let mut trans = conn.transaction().await?;
+    match (direction, marked) {
+        (MigrationDirection::Up, false) => {
+            let start = Instant::now();
+            migration.up(&mut trans).await?;
+            let duration = Some(start.elapsed());
+            record_migration(&trans, table, migration, duration, performed_by).await?;
+        }
+        (MigrationDirection::Down, false) => {
+            migration.down(&mut trans).await?;
+            unrecord_migration(&trans, table, migration).await?;
+        }
+        (MigrationDirection::Up, true) => {
+            record_migration(&trans, table, migration, None, performed_by).await?;
+        }
+        (MigrationDirection::Down, true) => {
+            unrecord_migration(&trans, table, migration).await?;
+        }
+    }
+    record_event(
+        &trans,
+        table,
+        migration,
+        direction,
+        marked,
+        None,
+        performed_by,
+    )
+    .await?;
+    trans.commit().await
+}
+
+impl<'a> TokioPostgresAdapter<'a> {
+    /// Run `migration` as in `run_in_transaction`. Failure is recorded in the
+    /// history table after the transaction is rolled back, logging a warning
+    /// if this fails.
+    async fn run_recorded(
+        &mut self,
+        migration: &dyn TokioPostgresMigration,
+        direction: MigrationDirection,
+        marked: bool,
+    ) -> Result<(), PostgresError> {
+        let table = &self.migration_metadata_table;
+        let performed_by = self.applied_by.as_deref();
+        let result =
+            run_in_transaction(self.conn, table, migration, direction, marked, performed_by).await;
+        if let Err(e) = &result {
+            let error = error_text(e);
+            // The error of the attempt itself is returned, so one from
+            // recording it can only be logged.
+            if let Err(history_error) = record_event(
+                &*self.conn,
+                table,
+                migration,
+                direction,
+                marked,
+                Some(&error),
+                performed_by,
+            )
+            .await
+            {
+                warn!(
+                    "Failed to record failure of migration {} in history: {}",
+                    migration.id(),
+                    history_error
+                );
+            }
+        }
+        result
+    }
+}
+
+#[async_trait]
+impl<'a> AsyncAdapter for TokioPostgresAdapter<'a> {
+    type MigrationType = dyn TokioPostgresMigration;
+
+    type Error = TokioPostgresAdapterError;
+
+    async fn applied_migrations(&mut self) -> Result<HashSet<Uuid>, Self::Error> {
+        let rows = self
+            .conn
+            .query(
+                format!("SELECT id FROM {};", self.migration_metadata_table).as_str(),
+                &[],
+            )
+            .await?;
+        Ok(rows.iter().map(|row| row.get(0)).collect())
+    }
+
+    async fn apply_migration(
+        &mut self,
+        migration: &Self::MigrationType,
+    ) -> Result<(), Self::Error> {
+        self.run_recorded(migration, MigrationDirection::Up, false)
+            .await
+    }
+
+    async fn revert_migration(
+        &mut self,
+        migration: &Self::MigrationType,
+    ) -> Result<(), Self::Error> {
+        self.run_recorded(migration, MigrationDirection::Down, false)
+            .await
+    }
+
+    async fn record_migration(
+        &mut self,
+        migration: &Self::MigrationType,
+    ) -> Result<(), Self::Error> {
+        self.run_recorded(migration, MigrationDirection::Up, true)
+            .await
+    }
+
+    async fn unrecord_migration(
+        &mut self,
+        migration: &Self::MigrationType,
+    ) -> Result<(), Self::Error> {
+        self.run_recorded(migration, MigrationDirection::Down, true)
+            .await
+    }
+
+    async fn applied_checksums(&mut self) -> Result<HashMap<Uuid, String>, Self::Error> {
+        let rows = self
+            .conn
+            .query(
+                format!(
+                    "SELECT id, checksum FROM {} WHERE checksum IS NOT NULL;",
+                    self.migration_metadata_table
+                )
+                .as_str(),
+                &[],
+            )
+            .await?;
+        Ok(rows.iter().map(|row| (row.get(0), row.get(1))).collect())
+    }
+
+    async fn applied_records(&mut self) -> Result<Vec<AppliedMigration>, Self::Error> {
+        let rows = self
+            .conn
+            .query(
+                format!(
+                    r#"
+                        SELECT id, checksum, description, applied_at, duration_us, applied_by
+                        FROM {}
+                        ORDER BY applied_at, id;
+                    "#,
+                    self.migration_metadata_table
+                )
+                .as_str(),
+                &[],
+            )
+            .await?;
+        Ok(rows
+            .iter()
+            .map(|row| AppliedMigration {
+                id: row.get(0),
+                checksum: row.get(1),
+                description: row.get(2),
+                applied_at: row.get::<_, Option<SystemTime>>(3),
+                duration: row
+                    .get::<_, Option<i64>>(4)
+                    .map(|us| Duration::from_micros(us as u64)),
+                applied_by: row.get(5),
+            })
+            .collect())
+    }
+
+    async fn history(&mut self) -> Result<Vec<MigrationEvent>, Self::Error> {
+        let rows = self
+            .conn
+            .query(
+                format!(
+                    r#"
+                        SELECT id, direction, marked, occurred_at, error, performed_by
+                        FROM {}_history
+                        ORDER BY seq;
+                    "#,
+                    self.migration_metadata_table
+                )
+                .as_str(),
+                &[],
+            )
+            .await?;
+        Ok(rows
+            .iter()
+            .map(|row| MigrationEvent {
+                id: row.get(0),
+                direction: match row.get(1) {
+                    "down" => MigrationDirection::Down,
+                    _ => MigrationDirection::Up,
+                },
+                marked: row.get(2),
+                occurred_at: row.get(3),
+                error: row.get(4),
+                performed_by: row.get(5),
+            })
+            .collect())
+    }
+
+    async fn lock(&mut self) -> Result<(), Self::Error> {
+        if let Some(key) = self.advisory_lock {
+            self.conn
+                .execute("SELECT pg_advisory_lock($1)", &[&key])
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn unlock(&mut self) -> Result<(), Self::Error> {
+        if let Some(key) = self.advisory_lock {
+            self.conn
+                .execute("SELECT pg_advisory_unlock($1)", &[&key])
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::Future;
+
+    use super::*;
+    use schemer::testing::*;
+    use schemer::{migration, test_schemer_async_adapter, AsyncMigrator};
+    use tokio_postgres::NoTls;
+
+    impl TokioPostgresMigration for TestMigration {}
+
+    struct FailingMigration;
+    migration!(
+        FailingMigration,
+        "4885e8ab-dafa-4d76-a565-2dee8b04ef60",
+        ["bc960dc8-0e4a-4182-a62a-8e776d1e2b30"],
+        "A migration that fails to apply."
+    );
+
+    #[async_trait]
+    impl TokioPostgresMigration for FailingMigration {
+        async fn up(&self, transaction: &mut Transaction<'_>) -> Result<(), PostgresError> {
+            transaction
+                .batch_execute("SELECT * FROM nonexistent;")
+                .await
+        }
+    }
+
+    impl<'a> AsyncTestAdapter for TokioPostgresAdapter<'a> {
+        fn mock_from(migration: TestMigration) -> Box<Self::MigrationType> {
+            Box::new(migration)
+        }
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    async fn build_test_connection() -> Client {
+        let (client, connection) =
+            tokio_postgres::connect("postgresql://postgres@localhost", NoTls)
+                .await
+                .unwrap();
+        tokio::spawn(connection);
+        client
+            .execute("SET search_path = pg_temp", &[])
+            .await
+            .unwrap();
+        client
+    }
+
+    async fn build_test_adapter(conn: &mut Client) -> TokioPostgresAdapter<'_> {
+        let mut adapter = TokioPostgresAdapter::new(conn, None);
+        adapter.init().await.unwrap();
+        adapter
+    }
+
+    test_schemer_async_adapter!(
+        block_on,
+        let mut conn = build_test_connection().await,
+        build_test_adapter(&mut conn).await);
+
+    #[test]
+    fn test_advisory_lock() {
+        block_on(async {
+            let key = 0x7465_7374_6b65_7900;
+            let id = Uuid::parse_str("bc960dc8-0e4a-4182-a62a-8e776d1e2b30").unwrap();
+            let mut conn = build_test_connection().await;
+            let mut waiting_conn = build_test_connection().await;
+            let mut unlocked_conn = build_test_connection().await;
+
+            let mut adapter = build_test_adapter(&mut conn).await;
+            adapter.set_advisory_lock(Some(key));
+            adapter.lock().await.unwrap();
+
+            let mut waiting_adapter = build_test_adapter(&mut waiting_conn).await;
+            waiting_adapter.set_advisory_lock(Some(key));
+            let mut waiting = AsyncMigrator::new(waiting_adapter);
+            waiting
+                .register(TokioPostgresAdapter::mock(id, HashSet::new()))
+                .unwrap();
+            let blocked = tokio::time::timeout(Duration::from_millis(200), waiting.up(None));
+            assert!(blocked.await.is_err());
+
+            let mut unlocked_adapter = build_test_adapter(&mut unlocked_conn).await;
+            unlocked_adapter.set_advisory_lock(None);
+            let mut unlocked = AsyncMigrator::new(unlocked_adapter);
+            unlocked
+                .register(TokioPostgresAdapter::mock(id, HashSet::new()))
+                .unwrap();
+            unlocked.up(None).await.unwrap();
+
+            adapter.unlock().await.unwrap();
+            let report = waiting.up(None).await.unwrap();
+            assert_eq!(report.executed.len(), 1);
+        });
+    }
+
+    #[test]
+    fn test_upgrade_metadata() {
+        block_on(async {
+            let mut conn = build_test_connection().await;
+            let id = Uuid::parse_str("bc960dc8-0e4a-4182-a62a-8e776d1e2b30").unwrap();
+            conn.batch_execute("CREATE TABLE _schemer (id uuid PRIMARY KEY);")
+                .await
+                .unwrap();
+            conn.execute("INSERT INTO _schemer (id) VALUES ($1)", &[&id])
+                .await
+                .unwrap();
+
+            let mut adapter = build_test_adapter(&mut conn).await;
+            adapter.init().await.unwrap();
+            assert_eq!(
+                adapter.applied_records().await.unwrap(),
+                vec![AppliedMigration::new(id)]
+            );
+            let migration = TokioPostgresAdapter::mock(
+                Uuid::parse_str("4885e8ab-dafa-4d76-a565-2dee8b04ef60").unwrap(),
+                HashSet::new(),
+            );
+            adapter.apply_migration(migration.as_ref()).await.unwrap();
+            let records = adapter.applied_records().await.unwrap();
+            assert_eq!(records.len(), 2);
+            assert!(records[0].applied_at.is_some());
+
+            let version: i32 = conn
+                .query_one("SELECT version FROM _schemer_version", &[])
+                .await
+                .unwrap()
+                .get(0);
+            assert_eq!(version, METADATA_VERSION);
+        });
+    }
+
+    #[test]
+    fn test_history() {
+        block_on(async {
+            let mut conn = build_test_connection().await;
+            let mut adapter = build_test_adapter(&mut conn).await;
+            adapter.set_applied_by(Some("test".into()));
+            let id = Uuid::parse_str("bc960dc8-0e4a-4182-a62a-8e776d1e2b30").unwrap();
+            let mut migrator = AsyncMigrator::new(adapter);
+            migrator
+                .register_multiple(vec![
+                    TokioPostgresAdapter::mock(id, HashSet::new()),
+                    Box::new(FailingMigration),
+                ])
+                .unwrap();
+
+            assert!(migrator.up(None).await.is_err());
+            migrator.down(None).await.unwrap();
+            migrator.mark_applied(&[id]).await.unwrap();
+
+            let history = migrator.history().await.unwrap();
+            let events: Vec<_> = history
+                .iter()
+                .map(|event| {
+                    (
+                        event.id,
+                        event.direction,
+                        event.marked,
+                        event.error.is_some(),
+                    )
+                })
+                .collect();
+            assert_eq!(
+                events,
+                vec![
+                    (id, MigrationDirection::Up, false, false),
+                    (FailingMigration.id(), MigrationDirection::Up, false, true),
+                    (id, MigrationDirection::Down, false, false),
+                    (id, MigrationDirection::Up, true, false),
+                ]
+            );
+            assert!(history[1].error.as_ref().unwrap().contains("nonexistent"));
+            assert!(history
+                .iter()
+                .all(|event| event.performed_by.as_deref() == Some("test")));
+        });
+    }
+
+    #[test]
+    fn test_history_failure() {
+        block_on(async {
+            let mut conn = build_test_connection().await;
+            let mut adapter = build_test_adapter(&mut conn).await;
+            adapter
+                .conn
+                .batch_execute("DROP TABLE _schemer_history;")
+                .await
+                .unwrap();
+
+            let error = adapter
+                .apply_migration(&FailingMigration)
+                .await
+                .unwrap_err();
+            assert!(error_text(&error).contains("nonexistent"));
+            assert!(adapter.history().await.is_err());
+            assert!(adapter.applied_migrations().await.unwrap().is_empty());
+        });
+    }
+
+    #[test]
+    fn test_applied_record_metadata() {
+        block_on(async {
+            let mut conn = build_test_connection().await;
+            let mut adapter = build_test_adapter(&mut conn).await;
+            adapter.set_applied_by(Some("test".into()));
+            let id = Uuid::parse_str("bc960dc8-0e4a-4182-a62a-8e776d1e2b30").unwrap();
+            let migration = TokioPostgresAdapter::mock(id, HashSet::new());
+            let before = SystemTime::now() - Duration::from_secs(60);
+
+            adapter.apply_migration(migration.as_ref()).await.unwrap();
+            let records = adapter.applied_records().await.unwrap();
+            assert_eq!(records.len(), 1);
+            let record = &records[0];
+            assert_eq!(record.id, migration.id());
+            assert_eq!(record.description.as_deref(), Some("Test Migration"));
+            assert!(record.applied_at.unwrap() > before);
+            assert!(record.duration.is_some());
+            assert_eq!(record.applied_by.as_deref(), Some("test"));
+        });
+    }
+}
@@ -0,0 +1,434 @@
+//! Asynchronous counterparts of `Adapter` and `Migrator`, for adapters built
+//! on async database drivers.
+
+use std::collections::{HashMap, HashSet};
+use std::time::Instant;
+
+use async_trait::async_trait;
+use daggy::petgraph::EdgeDirection;
+use log::info;
+use uuid::Uuid;
+
+use super::graph::{abort, Execution, IndexPlan, MigrationGraph};
+use super::{
+    release_lock, AppliedMigration, ChecksumMismatch, MergeMigration, Migration,
+    MigrationDirection, MigrationEvent, MigrationObserver, MigrationReport, MigrationStatus,
+    MigratorError, PlannedMigration,
+};
+
+/// Persistence of migration state and application of migrations through an
+/// async database driver. This mirrors `Adapter`, but its methods are async.
+///
+/// Implementations use the `async_trait` attribute, re-exported as
+/// `schemer::async_trait`.
+#[async_trait]
+pub trait AsyncAdapter: Send {
+    /// Type migrations must implement for this adapter.
+    type MigrationType: Migration + Send + Sync + ?Sized;
+
+    /// Type of errors returned by this adapter.
+    type Error: std::error::Error + 'static;
+
+    /// Returns the set of IDs for migrations that have been applied.
+    async fn applied_migrations(&mut self) -> Result<HashSet<Uuid>, Self::Error>;
+
+    /// Apply a single migration.
+    async fn apply_migration(&mut self, _: &Self::MigrationType) -> Result<(), Self::Error>;
+
+    /// Revert a single migration.
+    async fn revert_migration(&mut self, _: &Self::MigrationType) -> Result<(), Self::Error>;
+
+    /// Record a single migration as applied without applying it.
+    async fn record_migration(&mut self, _: &Self::MigrationType) -> Result<(), Self::Error>;
+
+    /// Record a single migration as not applied without reverting it.
+    async fn unrecord_migration(&mut self, _: &Self::MigrationType) -> Result<(), Self::Error>;
+
+    /// Returns the checksums stored for applied migrations that had one when
+    /// they were applied. Adapters that do not store checksums need not
+    /// implement this.
+    async fn applied_checksums(&mut self) -> Result<HashMap<Uuid, String>, Self::Error> {
+        Ok(HashMap::new())
+    }
+
+    /// See `Adapter::applied_records`.
+    async fn applied_records(&mut self) -> Result<Vec<AppliedMigration>, Self::Error> {
+        let mut checksums = self.applied_checksums().await?;
+        let mut ids: Vec<Uuid> = self.applied_migrations().await?.into_iter().collect();
+        ids.sort();
+        Ok(ids
+            .into_iter()
+            .map(|id| AppliedMigration {
+                checksum: checksums.remove(&id),
+                ..AppliedMigration::new(id)
+            })
+            .collect())
+    }
+
+    /// See `Adapter::history`.
+    async fn history(&mut self) -> Result<Vec<MigrationEvent>, Self::Error> {
+        Ok(vec![])
+    }
+
+    /// See `Adapter::lock`.
+    async fn lock(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// See `Adapter::unlock`.
+    async fn unlock(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// Asynchronous counterpart of `Migrator` for an `AsyncAdapter`.
+pub struct AsyncMigrator<T: AsyncAdapter> {
+    pub(crate) adapter: T,
+    graph: MigrationGraph<T::MigrationType>,
+    strict: bool,
+    single_head: bool,
+    observers: Vec<Box<dyn MigrationObserver + Send>>,
+}
+
+impl<T: AsyncAdapter> AsyncMigrator<T> {
+    /// Create an `AsyncMigrator` using the given `AsyncAdapter`.
+    pub fn new(adapter: T) -> AsyncMigrator<T> {
+        AsyncMigrator {
+            adapter,
+            graph: MigrationGraph::new(),
+            strict: false,
+            single_head: false,
+            observers: vec![],
+        }
+    }
+
+    /// Add an observer to be notified of events while migrating up or down.
+    /// Observers are notified in the order they were added.
+    pub fn add_observer(&mut self, observer: Box<dyn MigrationObserver + Send>) {
+        self.observers.push(observer);
+    }
+
+    /// See `Migrator::set_strict`.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    /// See `Migrator::set_single_head`.
+    pub fn set_single_head(&mut self, single_head: bool) {
+        self.single_head = single_head;
+    }
+
+    /// Register a migration into the dependency graph.
+    pub fn register(
+        &mut self,
+        migration: Box<T::MigrationType>,
+    ) -> Result<(), MigratorError<T::Error>> {
+        self.register_multiple(vec![migration])
+    }
+
+    /// Register multiple migrations into the dependency graph. Like
+    /// `Migrator::register_multiple`, registration is atomic.
+    pub fn register_multiple(
+        &mut self,
+        migrations: Vec<Box<T::MigrationType>>,
+    ) -> Result<(), MigratorError<T::Error>> {
+        self.graph
+            .register_multiple(migrations)
+            .map_err(MigratorError::Dependency)
+    }
+
+    /// See `Migrator::ancestors`.
+    pub fn ancestors(&self, id: Uuid) -> Result<Vec<Uuid>, MigratorError<T::Error>> {
+        self.graph.ancestors(id).map_err(MigratorError::Dependency)
+    }
+
+    /// See `Migrator::descendants`.
+    pub fn descendants(&self, id: Uuid) -> Result<Vec<Uuid>, MigratorError<T::Error>> {
+        self.graph
+            .descendants(id)
+            .map_err(MigratorError::Dependency)
+    }
+
+    /// See `Migrator::heads`.
+    pub fn heads(&self) -> Vec<Uuid> {
+        self.graph.externals(EdgeDirection::Outgoing)
+    }
+
+    /// See `Migrator::roots`.
+    pub fn roots(&self) -> Vec<Uuid> {
+        self.graph.externals(EdgeDirection::Incoming)
+    }
+
+    /// See `Migrator::dependencies_of`.
+    pub fn dependencies_of(&self, id: Uuid) -> Result<HashSet<Uuid>, MigratorError<T::Error>> {
+        self.graph
+            .dependencies_of(id)
+            .map_err(MigratorError::Dependency)
+    }
+
+    /// See `Migrator::check_single_head`.
+    pub fn check_single_head(&self) -> Result<(), MigratorError<T::Error>> {
+        self.graph
+            .check_single_head()
+            .map_err(MigratorError::Dependency)
+    }
+
+    /// See `Migrator::merge_heads`.
+    pub fn merge_heads(&self) -> Option<MergeMigration> {
+        self.graph.merge_heads()
+    }
+
+    /// See `Migrator::to_dot`.
+    pub fn to_dot(&self) -> String {
+        self.graph.render_dot(None)
+    }
+
+    /// See `Migrator::to_dot_with_state`.
+    pub async fn to_dot_with_state(&mut self) -> Result<String, MigratorError<T::Error>> {
+        let applied_migrations = self.adapter.applied_migrations().await?;
+        Ok(self.graph.render_dot(Some(&applied_migrations)))
+    }
+
+    /// See `Migrator::to_mermaid`.
+    pub fn to_mermaid(&self, target: Option<Uuid>) -> Result<String, MigratorError<T::Error>> {
+        self.graph
+            .render_mermaid(target)
+            .map_err(MigratorError::Dependency)
+    }
+
+    /// Check that the applied migrations are consistent with the dependency
+    /// graph and that none have changed since they were applied.
+    async fn check_adapter(
+        &mut self,
+        applied: &HashSet<Uuid>,
+    ) -> Result<(), MigratorError<T::Error>> {
+        let checksums = self.adapter.applied_checksums().await?;
+        self.graph
+            .check_applied(applied, &checksums)
+            .map_err(MigratorError::Dependency)
+    }
+
+    /// Read the applied migrations from the adapter, checking them as
+    /// configured like `Migrator` does.
+    async fn applied_migrations(&mut self) -> Result<HashSet<Uuid>, MigratorError<T::Error>> {
+        if self.single_head {
+            self.check_single_head()?;
+        }
+        let applied_migrations = self.adapter.applied_migrations().await?;
+        let checksums = self.adapter.applied_checksums().await?;
+        self.graph
+            .inspect_applied(&applied_migrations, &checksums, self.strict)
+            .map_err(MigratorError::Dependency)?;
+        Ok(applied_migrations)
+    }
+
+    /// See `Migrator::validate`.
+    pub async fn validate(&mut self) -> Result<(), MigratorError<T::Error>> {
+        let applied_migrations = self.adapter.applied_migrations().await?;
+        self.check_adapter(&applied_migrations).await
+    }
+
+    /// See `Migrator::plan_up`.
+    pub async fn plan_up(
+        &mut self,
+        to: Option<Uuid>,
+    ) -> Result<Vec<PlannedMigration>, MigratorError<T::Error>> {
+        let (indices, _) = self.up_indices(to).await?;
+        Ok(self.graph.planned(&indices, MigrationDirection::Up))
+    }
+
+    /// See `Migrator::plan_down`.
+    pub async fn plan_down(
+        &mut self,
+        to: Option<Uuid>,
+    ) -> Result<Vec<PlannedMigration>, MigratorError<T::Error>> {
+        let (indices, _) = self.down_indices(to).await?;
+        Ok(self.graph.planned(&indices, MigrationDirection::Down))
+    }
+
+    /// See `Migrator::checksum_mismatches`.
+    pub async fn checksum_mismatches(
+        &mut self,
+    ) -> Result<Vec<ChecksumMismatch>, MigratorError<T::Error>> {
+        let checksums = self.adapter.applied_checksums().await?;
+        Ok(self.graph.mismatched(&checksums))
+    }
+
+    /// See `Migrator::applied_records`.
+    pub async fn applied_records(
+        &mut self,
+    ) -> Result<Vec<AppliedMigration>, MigratorError<T::Error>> {
+        Ok(self.adapter.applied_records().await?)
+    }
+
+    /// See `Migrator::history`.
+    pub async fn history(&mut self) -> Result<Vec<MigrationEvent>, MigratorError<T::Error>> {
+        Ok(self.adapter.history().await?)
+    }
+
+    /// See `Migrator::status`.
+    pub async fn status(&mut self) -> Result<MigrationStatus, MigratorError<T::Error>> {
+        let applied_migrations = self.adapter.applied_migrations().await?;
+        Ok(self.graph.status(&applied_migrations))
+    }
+
+    /// See `Migrator::mark_applied`.
+    pub async fn mark_applied(&mut self, ids: &[Uuid]) -> Result<(), MigratorError<T::Error>> {
+        self.adapter.lock().await?;
+        let result = self.mark_applied_locked(ids).await;
+        release_lock(result, self.adapter.unlock().await)
+    }
+
+    async fn mark_applied_locked(&mut self, ids: &[Uuid]) -> Result<(), MigratorError<T::Error>> {
+        let applied_migrations = self.adapter.applied_migrations().await?;
+        let indices = self
+            .graph
+            .marked_indices(ids, &applied_migrations, MigrationDirection::Up)
+            .map_err(MigratorError::Dependency)?;
+        for idx in indices {
+            let migration = &self.graph.dependencies[idx];
+            info!("Marking migration {} as applied", migration.id());
+            self.adapter.record_migration(migration).await?;
+        }
+
+        Ok(())
+    }
+
+    /// See `Migrator::mark_reverted`.
+    pub async fn mark_reverted(&mut self, ids: &[Uuid]) -> Result<(), MigratorError<T::Error>> {
+        self.adapter.lock().await?;
+        let result = self.mark_reverted_locked(ids).await;
+        release_lock(result, self.adapter.unlock().await)
+    }
+
+    async fn mark_reverted_locked(&mut self, ids: &[Uuid]) -> Result<(), MigratorError<T::Error>> {
+        let applied_migrations = self.adapter.applied_migrations().await?;
+        let indices = self
+            .graph
+            .marked_indices(ids, &applied_migrations, MigrationDirection::Down)
+            .map_err(MigratorError::Dependency)?;
+        for idx in indices {
+            let migration = &self.graph.dependencies[idx];
+            info!("Marking migration {} as reverted", migration.id());
+            self.adapter.unrecord_migration(migration).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Apply migrations as necessary to so that the specified migration is
+    /// applied (inclusive), in the same order and holding the adapter's lock
+    /// like `Migrator::up`.
+    ///
+    /// If `to` is `None`, apply all registered migrations.
+    pub async fn up(
+        &mut self,
+        to: Option<Uuid>,
+    ) -> Result<MigrationReport, MigratorError<T::Error>> {
+        info!("Migrating up to target: {:?}", to);
+        if let Err(e) = self.adapter.lock().await {
+            return abort(&mut self.observers, MigratorError::Adapter(e));
+        }
+        let result = self.up_locked(to).await;
+        release_lock(result, self.adapter.unlock().await)
+    }
+
+    async fn up_locked(
+        &mut self,
+        to: Option<Uuid>,
+    ) -> Result<MigrationReport, MigratorError<T::Error>> {
+        let (indices, skipped) = match self.up_indices(to).await {
+            Ok(indices) => indices,
+            Err(e) => return abort(&mut self.observers, e),
+        };
+        self.execute(indices, skipped, MigrationDirection::Up).await
+    }
+
+    /// Revert migrations as necessary so that no migrations dependent on the
+    /// specified migration are applied, in the same order and holding the
+    /// adapter's lock like `Migrator::down`.
+    ///
+    /// If `to` is `None`, revert all applied migrations.
+    pub async fn down(
+        &mut self,
+        to: Option<Uuid>,
+    ) -> Result<MigrationReport, MigratorError<T::Error>> {
+        info!("Migrating down to target: {:?}", to);
+        if let Err(e) = self.adapter.lock().await {
+            return abort(&mut self.observers, MigratorError::Adapter(e));
+        }
+        let result = self.down_locked(to).await;
+        release_lock(result, self.adapter.unlock().await)
+    }
+
+    async fn down_locked(
+        &mut self,
+        to: Option<Uuid>,
+    ) -> Result<MigrationReport, MigratorError<T::Error>> {
+        let (indices, skipped) = match self.down_indices(to).await {
+            Ok(indices) => indices,
+            Err(e) => return abort(&mut self.observers, e),
+        };
+        self.execute(indices, skipped, MigrationDirection::Down)
+            .await
+    }
+
+    /// Indices of the migrations that `up` would apply for `to`, and IDs of
+    /// those it would skip because they are already applied.
+    async fn up_indices(&mut self, to: Option<Uuid>) -> Result<IndexPlan, MigratorError<T::Error>> {
+        let applied_migrations = self.applied_migrations().await?;
+        self.graph
+            .up_indices(to, &applied_migrations)
+            .map_err(MigratorError::Dependency)
+    }
+
+    /// Indices of the migrations that `down` would revert for `to`, and IDs
+    /// of those it would skip because they are not applied. Fails if any of
+    /// them is irreversible.
+    async fn down_indices(
+        &mut self,
+        to: Option<Uuid>,
+    ) -> Result<IndexPlan, MigratorError<T::Error>> {
+        let applied_migrations = self.applied_migrations().await?;
+        let (indices, skipped) = self
+            .graph
+            .down_indices(to, &applied_migrations)
+            .map_err(MigratorError::Dependency)?;
+        self.graph.check_reversible(&indices)?;
+        Ok((indices, skipped))
+    }
+
+    /// Apply or revert the migrations at `indices` in order.
+    async fn execute(
+        &mut self,
+        indices: Vec<daggy::NodeIndex>,
+        skipped: Vec<Uuid>,
+        direction: MigrationDirection,
+    ) -> Result<MigrationReport, MigratorError<T::Error>> {
+        let plan = self.graph.planned(&indices, direction);
+        let mut execution = Execution::start(&mut self.observers, &plan, direction, skipped);
+
+        for (idx, planned) in indices.into_iter().zip(plan) {
+            let migration = &self.graph.dependencies[idx];
+            execution.before(&planned);
+
+            let start = Instant::now();
+            let result = match direction {
+                MigrationDirection::Up => {
+                    info!("Applying migration {}", planned.id);
+                    self.adapter.apply_migration(migration).await
+                }
+                MigrationDirection::Down => {
+                    info!("Reverting migration {}", planned.id);
+                    self.adapter.revert_migration(migration).await
+                }
+            };
+            match result {
+                Ok(()) => execution.succeeded(planned, start.elapsed()),
+                Err(e) => return Err(execution.failed(planned, e)),
+            }
+        }
+
+        Ok(execution.finish())
+    }
+}
@@ -0,0 +1,164 @@
+//! Rendering of the migration dependency graph for visualization.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+
+use daggy::petgraph::EdgeDirection;
+use daggy::NodeIndex;
+use uuid::Uuid;
+
+use super::graph::MigrationGraph;
+use super::{Adapter, DependencyError, Migration, Migrator, MigratorError};
+
+/// Number of leading characters of a migration ID shown in node labels.
+const SHORT_ID_LEN: usize = 8;
+
+fn short_id(id: &Uuid) -> String {
+    id.to_string()[..SHORT_ID_LEN].to_owned()
+}
+
+/// Escape a string for use inside a double-quoted DOT ID.
+fn escape_dot(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Mermaid node ID for a migration.
+fn mermaid_id(id: &Uuid) -> String {
+    format!("m{}", id.simple())
+}
+
+/// Escape a string for use inside a double-quoted Mermaid label.
+fn escape_mermaid(s: &str) -> String {
+    s.replace('"', "#quot;")
+}
+
+/// Migrations, as ID and description, and dependency edges between them, both
+/// in the order `up` would apply them.
+struct Graph {
+    nodes: Vec<(Uuid, &'static str)>,
+    edges: Vec<(Uuid, Uuid)>,
+}
+
+impl<M: Migration + ?Sized> MigrationGraph<M> {
+    /// All registered migrations and dependency edges between them, or if
+    /// `ids` is given only those among these migrations.
+    fn export_graph(&self, ids: Option<&HashSet<Uuid>>) -> Graph {
+        let ordered: Vec<NodeIndex> = self
+            .ordered_indices()
+            .into_iter()
+            .filter(|idx| ids.map_or(true, |ids| ids.contains(&self.dependencies[*idx].id())))
+            .collect();
+        let positions: HashMap<_, _> = ordered
+            .iter()
+            .enumerate()
+            .map(|(pos, idx)| (*idx, pos))
+            .collect();
+
+        let mut nodes = Vec::with_capacity(ordered.len());
+        let mut edges = vec![];
+        for idx in &ordered {
+            let migration = &self.dependencies[*idx];
+            nodes.push((migration.id(), migration.description()));
+
+            let mut parents: Vec<_> = self
+                .dependencies
+                .graph()
+                .neighbors_directed(*idx, EdgeDirection::Incoming)
+                .filter(|parent_idx| positions.contains_key(parent_idx))
+                .collect();
+            parents.sort_by_key(|parent_idx| positions[parent_idx]);
+            edges.extend(
+                parents
+                    .into_iter()
+                    .map(|parent_idx| (self.dependencies[parent_idx].id(), migration.id())),
+            );
+        }
+
+        Graph { nodes, edges }
+    }
+
+    /// See `Migrator::to_dot` and `Migrator::to_dot_with_state`.
+    pub(crate) fn render_dot(&self, applied: Option<&HashSet<Uuid>>) -> String {
+        let Graph { nodes, edges } = self.export_graph(None);
+
+        let mut dot = String::from("digraph migrations {\n    node [shape=box];\n");
+        for (id, description) in nodes {
+            let style = match applied {
+                Some(applied) if applied.contains(&id) => ", style=filled, fillcolor=palegreen",
+                Some(_) => ", style=filled, fillcolor=lightgoldenrod1",
+                None => "",
+            };
+            writeln!(
+                dot,
+                "    \"{}\" [label=\"{}\\n{}\"{}];",
+                id,
+                escape_dot(description),
+                short_id(&id),
+                style
+            )
+            .expect("Impossible: writing to a String");
+        }
+        for (from, to) in edges {
+            writeln!(dot, "    \"{}\" -> \"{}\";", from, to)
+                .expect("Impossible: writing to a String");
+        }
+        dot.push_str("}\n");
+
+        dot
+    }
+
+    /// See `Migrator::to_mermaid`.
+    pub(crate) fn render_mermaid(&self, target: Option<Uuid>) -> Result<String, DependencyError> {
+        let target_ids = match target {
+            Some(_) => Some(self.induced_stream(target, EdgeDirection::Incoming)?),
+            None => None,
+        };
+        let Graph { nodes, edges } = self.export_graph(target_ids.as_ref());
+
+        let mut mermaid = String::from("graph TD\n");
+        for (id, description) in nodes {
+            writeln!(
+                mermaid,
+                "    {}[\"{}<br/>{}\"]",
+                mermaid_id(&id),
+                escape_mermaid(description),
+                short_id(&id)
+            )
+            .expect("Impossible: writing to a String");
+        }
+        for (from, to) in edges {
+            writeln!(mermaid, "    {} --> {}", mermaid_id(&from), mermaid_id(&to))
+                .expect("Impossible: writing to a String");
+        }
+
+        Ok(mermaid)
+    }
+}
+
+impl<T: Adapter> Migrator<T> {
+    /// Render the dependency graph in Graphviz DOT format. Each migration is
+    /// labelled with its description and the start of its ID, and edges point
+    /// from dependencies to their dependents.
+    pub fn to_dot(&self) -> String {
+        self.graph.render_dot(None)
+    }
+
+    /// Render the dependency graph in Graphviz DOT format as in `to_dot`, with
+    /// applied migrations filled green and pending migrations filled yellow.
+    pub fn to_dot_with_state(&mut self) -> Result<String, MigratorError<T::Error>> {
+        let applied_migrations = self.adapter.applied_migrations()?;
+        Ok(self.graph.render_dot(Some(&applied_migrations)))
+    }
+
+    /// Render the dependency graph as a Mermaid `graph TD` flowchart. Each
+    /// migration is labelled with its description and the start of its ID,
+    /// and edges point from dependencies to their dependents.
+    ///
+    /// If `target` is given, only the target and the migrations it depends on
+    /// (the migrations `up` would consider for this target) are rendered.
+    pub fn to_mermaid(&self, target: Option<Uuid>) -> Result<String, MigratorError<T::Error>> {
+        self.graph
+            .render_mermaid(target)
+            .map_err(MigratorError::Dependency)
+    }
+}
@@ -0,0 +1,570 @@
+//! The migration dependency graph, independent of how migrations are applied.
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+use std::time::Duration;
+
+use daggy::petgraph::EdgeDirection;
+use daggy::{Dag, NodeIndex};
+use log::{debug, warn};
+use uuid::Uuid;
+
+use super::{
+    ChecksumMismatch, DependencyError, ExecutedMigration, Migration, MigrationDirection,
+    MigrationObserver, MigrationReport, MigrationStatus, MigratorError, PlannedMigration,
+};
+
+/// Indices of the migrations to apply or revert, in order, and IDs of those
+/// skipped because they are already in the target state.
+pub(crate) type IndexPlan = (Vec<NodeIndex>, Vec<Uuid>);
+
+/// Registered migrations and the dependency relations between them, shared by
+/// the synchronous and asynchronous migrators.
+pub(crate) struct MigrationGraph<M: Migration + ?Sized> {
+    pub(crate) dependencies: Dag<Box<M>, ()>,
+    pub(crate) id_map: HashMap<Uuid, NodeIndex>,
+}
+
+impl<M: Migration + ?Sized> MigrationGraph<M> {
+    pub(crate) fn new() -> MigrationGraph<M> {
+        MigrationGraph {
+            dependencies: Dag::new(),
+            id_map: HashMap::new(),
+        }
+    }
+
+    /// Register multiple migrations into the dependency graph atomically: if
+    /// any migration can not be registered, none are and the graph is left
+    /// unchanged.
+    pub(crate) fn register_multiple(
+        &mut self,
+        migrations: Vec<Box<M>>,
+    ) -> Result<(), DependencyError> {
+        let mut added = Vec::with_capacity(migrations.len());
+        let result = self.add_migrations(migrations, &mut added);
+        if result.is_err() {
+            // Remove nodes in reverse order of addition, so that removal never
+            // changes the indices of remaining nodes.
+            for (id, idx) in added.into_iter().rev() {
+                self.dependencies.remove_node(idx);
+                self.id_map.remove(&id);
+            }
+        }
+
+        result
+    }
+
+    /// Add nodes for `migrations` and edges for their dependencies, recording
+    /// each added node in `added` so that it can be removed on error.
+    fn add_migrations(
+        &mut self,
+        migrations: Vec<Box<M>>,
+        added: &mut Vec<(Uuid, NodeIndex)>,
+    ) -> Result<(), DependencyError> {
+        for migration in migrations {
+            let id = migration.id();
+            debug!("Registering migration {}", id);
+            if self.id_map.contains_key(&id) {
+                return Err(DependencyError::DuplicateId(id));
+            }
+            let migration_idx = self.dependencies.add_node(migration);
+            self.id_map.insert(id, migration_idx);
+            added.push((id, migration_idx));
+        }
+
+        for (id, migration_idx) in added.iter() {
+            let mut depends: Vec<_> = self.dependencies[*migration_idx]
+                .dependencies()
+                .into_iter()
+                .collect();
+            depends.sort();
+            for d in depends {
+                let parent_idx = self.id_map.get(&d).ok_or(DependencyError::UnknownId(d))?;
+                self.dependencies
+                    .add_edge(*parent_idx, *migration_idx, ())
+                    .map_err(|_| DependencyError::Cycle { from: d, to: *id })?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Collect the ids of recursively dependent migrations in `dir` induced
+    /// starting from `id`. If `dir` is `Incoming`, this is all ancestors
+    /// (dependencies); if `Outgoing`, this is all descendents (dependents).
+    /// If `id` is `None`, this is all migrations starting from the sources or
+    /// the sinks, respectively.
+    pub(crate) fn induced_stream(
+        &self,
+        id: Option<Uuid>,
+        dir: EdgeDirection,
+    ) -> Result<HashSet<Uuid>, DependencyError> {
+        let mut target_ids = HashSet::new();
+        match id {
+            Some(id) => {
+                if !self.id_map.contains_key(&id) {
+                    return Err(DependencyError::UnknownId(id));
+                }
+                target_ids.insert(id);
+            }
+            // This will eventually yield all migrations, so could be optimized.
+            None => target_ids.extend(
+                self.dependencies
+                    .graph()
+                    .externals(dir.opposite())
+                    .map(|idx| self.dependencies[idx].id()),
+            ),
+        }
+
+        let mut to_visit: VecDeque<_> = target_ids
+            .iter()
+            .map(|id| *self.id_map.get(id).expect("ID map is malformed"))
+            .collect();
+        while !to_visit.is_empty() {
+            let idx = to_visit.pop_front().expect("Impossible: not empty");
+            let id = self.dependencies[idx].id();
+            target_ids.insert(id);
+            to_visit.extend(self.dependencies.graph().neighbors_directed(idx, dir));
+        }
+
+        Ok(target_ids)
+    }
+
+    /// IDs of migrations in `ids`, in the order `up` would apply them.
+    fn in_order(&self, ids: &HashSet<Uuid>) -> Vec<Uuid> {
+        self.ordered_indices()
+            .into_iter()
+            .map(|idx| self.dependencies[idx].id())
+            .filter(|id| ids.contains(id))
+            .collect()
+    }
+
+    /// All migrations that `id` depends on, directly or indirectly, in the
+    /// order `up` would apply them.
+    pub(crate) fn ancestors(&self, id: Uuid) -> Result<Vec<Uuid>, DependencyError> {
+        let mut ancestors = self.induced_stream(Some(id), EdgeDirection::Incoming)?;
+        ancestors.remove(&id);
+        Ok(self.in_order(&ancestors))
+    }
+
+    /// All migrations that depend on `id`, directly or indirectly, in the
+    /// order `up` would apply them.
+    pub(crate) fn descendants(&self, id: Uuid) -> Result<Vec<Uuid>, DependencyError> {
+        let mut descendants = self.induced_stream(Some(id), EdgeDirection::Outgoing)?;
+        descendants.remove(&id);
+        Ok(self.in_order(&descendants))
+    }
+
+    /// Migrations with no neighbors in `dir`, in the order `up` would apply
+    /// them.
+    pub(crate) fn externals(&self, dir: EdgeDirection) -> Vec<Uuid> {
+        let graph = self.dependencies.graph();
+        self.ordered_indices()
+            .into_iter()
+            .filter(|idx| graph.neighbors_directed(*idx, dir).next().is_none())
+            .map(|idx| self.dependencies[idx].id())
+            .collect()
+    }
+
+    /// Direct dependencies of the migration `id`.
+    pub(crate) fn dependencies_of(&self, id: Uuid) -> Result<HashSet<Uuid>, DependencyError> {
+        let idx = self.id_map.get(&id).ok_or(DependencyError::UnknownId(id))?;
+        Ok(self
+            .dependencies
+            .graph()
+            .neighbors_directed(*idx, EdgeDirection::Incoming)
+            .map(|parent_idx| self.dependencies[parent_idx].id())
+            .collect())
+    }
+
+    /// Indices of all migrations in a deterministic topological order. Among
+    /// migrations whose dependencies all precede them, those registered
+    /// earlier come first.
+    pub(crate) fn ordered_indices(&self) -> Vec<NodeIndex> {
+        let graph = self.dependencies.graph();
+        let mut in_degrees: HashMap<_, _> = graph
+            .node_indices()
+            .map(|idx| {
+                let in_degree = graph
+                    .neighbors_directed(idx, EdgeDirection::Incoming)
+                    .count();
+                (idx, in_degree)
+            })
+            .collect();
+        let mut ready: BinaryHeap<_> = in_degrees
+            .iter()
+            .filter(|(_, in_degree)| **in_degree == 0)
+            .map(|(idx, _)| Reverse(*idx))
+            .collect();
+
+        let mut ordered = Vec::with_capacity(graph.node_count());
+        while let Some(Reverse(idx)) = ready.pop() {
+            ordered.push(idx);
+            for child_idx in graph.neighbors_directed(idx, EdgeDirection::Outgoing) {
+                let in_degree = in_degrees
+                    .get_mut(&child_idx)
+                    .expect("Impossible: node is in graph");
+                *in_degree -= 1;
+                if *in_degree == 0 {
+                    ready.push(Reverse(child_idx));
+                }
+            }
+        }
+        assert_eq!(
+            ordered.len(),
+            graph.node_count(),
+            "Impossible: dependencies are a DAG"
+        );
+
+        ordered
+    }
+
+    /// Indices of migrations to apply, in order, so that the migration `to`
+    /// (or all migrations, if `None`) is applied given the set of `applied`
+    /// migrations, along with the IDs of those in the target already applied.
+    pub(crate) fn up_indices(
+        &self,
+        to: Option<Uuid>,
+        applied: &HashSet<Uuid>,
+    ) -> Result<IndexPlan, DependencyError> {
+        let target_ids = self.induced_stream(to, EdgeDirection::Incoming)?;
+
+        Ok(self.partition(
+            self.ordered_indices()
+                .into_iter()
+                .filter(|idx| target_ids.contains(&self.dependencies[*idx].id())),
+            |id| !applied.contains(id),
+        ))
+    }
+
+    /// Indices of migrations to revert, in order, so that no migrations
+    /// dependent on `to` (or no migrations at all, if `None`) remain applied
+    /// given the set of `applied` migrations, along with the IDs of those in
+    /// the target not applied.
+    pub(crate) fn down_indices(
+        &self,
+        to: Option<Uuid>,
+        applied: &HashSet<Uuid>,
+    ) -> Result<IndexPlan, DependencyError> {
+        let mut target_ids = self.induced_stream(to, EdgeDirection::Outgoing)?;
+        if let Some(sink_id) = to {
+            target_ids.remove(&sink_id);
+        }
+
+        Ok(self.partition(
+            self.ordered_indices()
+                .into_iter()
+                .rev()
+                .filter(|idx| target_ids.contains(&self.dependencies[*idx].id())),
+            |id| applied.contains(id),
+        ))
+    }
+
+    /// Split `indices` into those of migrations that are `pending` and the IDs
+    /// of those that are not, preserving order.
+    fn partition<I, F>(&self, indices: I, pending: F) -> (Vec<NodeIndex>, Vec<Uuid>)
+    where
+        I: Iterator<Item = NodeIndex>,
+        F: Fn(&Uuid) -> bool,
+    {
+        let mut pending_indices = vec![];
+        let mut skipped = vec![];
+        for idx in indices {
+            let id = self.dependencies[idx].id();
+            if pending(&id) {
+                pending_indices.push(idx);
+            } else {
+                skipped.push(id);
+            }
+        }
+        (pending_indices, skipped)
+    }
+
+    /// IDs in `applied` that are not registered, in sorted order.
+    pub(crate) fn orphaned(&self, applied: &HashSet<Uuid>) -> Vec<Uuid> {
+        let mut orphaned: Vec<_> = applied
+            .iter()
+            .filter(|id| !self.id_map.contains_key(id))
+            .cloned()
+            .collect();
+        orphaned.sort();
+        orphaned
+    }
+
+    /// Check that all `applied` migrations are registered and that all of
+    /// their dependencies are also applied.
+    pub(crate) fn check_consistency(&self, applied: &HashSet<Uuid>) -> Result<(), DependencyError> {
+        if let Some(id) = self.orphaned(applied).into_iter().next() {
+            return Err(DependencyError::UnknownApplied(id));
+        }
+
+        for idx in self.ordered_indices() {
+            let id = self.dependencies[idx].id();
+            if !applied.contains(&id) {
+                continue;
+            }
+            for parent_idx in self
+                .dependencies
+                .graph()
+                .neighbors_directed(idx, EdgeDirection::Incoming)
+            {
+                let dependency = self.dependencies[parent_idx].id();
+                if !applied.contains(&dependency) {
+                    return Err(DependencyError::UnappliedDependency { id, dependency });
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Registered migrations whose current checksum differs from the one
+    /// stored in `checksums`, in sorted order.
+    pub(crate) fn mismatched(&self, checksums: &HashMap<Uuid, String>) -> Vec<ChecksumMismatch> {
+        let mut mismatches: Vec<_> = checksums
+            .iter()
+            .filter_map(|(id, stored)| {
+                let idx = self.id_map.get(id)?;
+                let current = self.dependencies[*idx].checksum();
+                if current.as_ref() == Some(stored) {
+                    None
+                } else {
+                    Some(ChecksumMismatch {
+                        id: *id,
+                        stored: stored.clone(),
+                        current,
+                    })
+                }
+            })
+            .collect();
+        mismatches.sort_by_key(|m| m.id);
+        mismatches
+    }
+
+    /// Check that the applied migrations are consistent with the dependency
+    /// graph and that none of those with stored `checksums` have changed.
+    pub(crate) fn check_applied(
+        &self,
+        applied: &HashSet<Uuid>,
+        checksums: &HashMap<Uuid, String>,
+    ) -> Result<(), DependencyError> {
+        self.check_consistency(applied)?;
+        if let Some(mismatch) = self.mismatched(checksums).into_iter().next() {
+            return Err(DependencyError::ChecksumMismatch {
+                id: mismatch.id,
+                stored: mismatch.stored,
+                current: mismatch.current,
+            });
+        }
+        Ok(())
+    }
+
+    /// Check the applied migrations a migrator read before planning a run. In
+    /// `strict` mode they must pass `check_applied`, otherwise any that are
+    /// not registered or have changed are only logged.
+    pub(crate) fn inspect_applied(
+        &self,
+        applied: &HashSet<Uuid>,
+        checksums: &HashMap<Uuid, String>,
+        strict: bool,
+    ) -> Result<(), DependencyError> {
+        if strict {
+            return self.check_applied(applied, checksums);
+        }
+        for id in self.orphaned(applied) {
+            warn!("Applied migration {} is not registered", id);
+        }
+        for mismatch in self.mismatched(checksums) {
+            warn!(
+                "Applied migration {} has changed since it was applied",
+                mismatch.id
+            );
+        }
+        Ok(())
+    }
+
+    /// Check that the registered migrations have at most one head.
+    pub(crate) fn check_single_head(&self) -> Result<(), DependencyError> {
+        let heads = self.externals(EdgeDirection::Outgoing);
+        if heads.len() > 1 {
+            return Err(DependencyError::MultipleHeads(heads));
+        }
+        Ok(())
+    }
+
+    /// Fail if any of the migrations at `indices` is irreversible.
+    pub(crate) fn check_reversible<E>(&self, indices: &[NodeIndex]) -> Result<(), MigratorError<E>>
+    where
+        E: std::error::Error + 'static,
+    {
+        match indices
+            .iter()
+            .map(|idx| &self.dependencies[*idx])
+            .find(|migration| !migration.reversible())
+        {
+            Some(migration) => Err(MigratorError::Irreversible {
+                id: migration.id(),
+                description: migration.description(),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    pub(crate) fn planned(
+        &self,
+        indices: &[NodeIndex],
+        direction: MigrationDirection,
+    ) -> Vec<PlannedMigration> {
+        indices
+            .iter()
+            .map(|idx| {
+                let migration = &self.dependencies[*idx];
+                PlannedMigration {
+                    id: migration.id(),
+                    description: migration.description(),
+                    direction,
+                }
+            })
+            .collect()
+    }
+
+    /// Which registered migrations are applied or pending given the set of
+    /// `applied` migrations, and which applied migrations are orphaned.
+    pub(crate) fn status(&self, applied: &HashSet<Uuid>) -> MigrationStatus {
+        let mut status = MigrationStatus {
+            orphaned: self.orphaned(applied),
+            ..Default::default()
+        };
+        for idx in self.ordered_indices() {
+            let id = self.dependencies[idx].id();
+            if applied.contains(&id) {
+                status.applied.push(id);
+            } else {
+                status.pending.push(id);
+            }
+        }
+        status
+    }
+
+    /// Indices of the migrations among `ids` that are not yet in the state
+    /// `direction` leads to given the `applied` migrations, in the order they
+    /// would be applied or reverted in that direction.
+    pub(crate) fn marked_indices(
+        &self,
+        ids: &[Uuid],
+        applied: &HashSet<Uuid>,
+        direction: MigrationDirection,
+    ) -> Result<Vec<NodeIndex>, DependencyError> {
+        for id in ids {
+            if !self.id_map.contains_key(id) {
+                return Err(DependencyError::UnknownId(*id));
+            }
+        }
+
+        let mut indices: Vec<_> = self
+            .ordered_indices()
+            .into_iter()
+            .filter(|idx| {
+                let id = self.dependencies[*idx].id();
+                let marked = match direction {
+                    MigrationDirection::Up => !applied.contains(&id),
+                    MigrationDirection::Down => applied.contains(&id),
+                };
+                marked && ids.contains(&id)
+            })
+            .collect();
+        if let MigrationDirection::Down = direction {
+            indices.reverse();
+        }
+        Ok(indices)
+    }
+}
+
+/// Progress of a run of `up` or `down`, which notifies the observers and
+/// assembles the report as the migrator executes each planned migration.
+pub(crate) struct Execution<'a, O: MigrationObserver + ?Sized> {
+    observers: &'a mut [Box<O>],
+    report: MigrationReport,
+}
+
+impl<'a, O: MigrationObserver + ?Sized> Execution<'a, O> {
+    /// Start executing `plan`, having skipped the migrations `skipped`.
+    pub(crate) fn start(
+        observers: &'a mut [Box<O>],
+        plan: &[PlannedMigration],
+        direction: MigrationDirection,
+        skipped: Vec<Uuid>,
+    ) -> Self {
+        for observer in observers.iter_mut() {
+            observer.on_plan(plan);
+        }
+        Execution {
+            observers,
+            report: MigrationReport {
+                direction,
+                executed: Vec::with_capacity(plan.len()),
+                skipped,
+            },
+        }
+    }
+
+    pub(crate) fn before(&mut self, planned: &PlannedMigration) {
+        for observer in self.observers.iter_mut() {
+            observer.before_migration(planned);
+        }
+    }
+
+    pub(crate) fn succeeded(&mut self, planned: PlannedMigration, duration: Duration) {
+        let executed = ExecutedMigration {
+            id: planned.id,
+            description: planned.description,
+            direction: planned.direction,
+            duration,
+        };
+        for observer in self.observers.iter_mut() {
+            observer.after_migration(&executed);
+        }
+        self.report.executed.push(executed);
+    }
+
+    /// Stop after `planned` failed with `error`, which is returned as a
+    /// `MigratorError`.
+    pub(crate) fn failed<E: std::error::Error + 'static>(
+        self,
+        planned: PlannedMigration,
+        error: E,
+    ) -> MigratorError<E> {
+        for observer in self.observers.iter_mut() {
+            observer.on_error(Some(&planned), &error);
+        }
+        MigratorError::Migration {
+            id: planned.id,
+            description: planned.description,
+            direction: planned.direction,
+            error,
+        }
+    }
+
+    pub(crate) fn finish(self) -> MigrationReport {
+        for observer in self.observers.iter_mut() {
+            observer.on_complete(&self.report);
+        }
+        self.report
+    }
+}
+
+/// Notify `observers` of an error that stopped `up` or `down` before any
+/// migration was executed, and return it.
+pub(crate) fn abort<R, E, O>(
+    observers: &mut [Box<O>],
+    error: MigratorError<E>,
+) -> Result<R, MigratorError<E>>
+where
+    E: std::error::Error + 'static,
+    O: MigrationObserver + ?Sized,
+{
+    for observer in observers.iter_mut() {
+        observer.on_error(None, &error);
+    }
+    Err(error)
+}
@@ -4,23 +4,39 @@
 //! To use with a specific database, an adapter is required. Known adapter
 //! crates:
 //!
+//! - MySQL and MariaDB: [`schemer-mysql`](https://crates.io/crates/schemer-mysql)
 //! - PostgreSQL: [`schemer-postgres`](https://crates.io/crates/schemer-postgres)
+//! - PostgreSQL (async, tokio-postgres): [`schemer-tokio-postgres`](https://crates.io/crates/schemer-tokio-postgres)
 //! - SQLite: [`schemer-rusqlite`](https://crates.io/crates/schemer-rusqlite)
+//! - PostgreSQL, SQLite and MySQL (Diesel): [`schemer-diesel`](https://crates.io/crates/schemer-diesel)
+//! - PostgreSQL, SQLite and MySQL (async, sqlx): [`schemer-sqlx`](https://crates.io/crates/schemer-sqlx)
 #![warn(clippy::all)]
 #![forbid(unsafe_code)]
 
-use std::collections::{HashMap, HashSet, VecDeque};
+use std::collections::{HashMap, HashSet};
 use std::fmt::{Debug, Display};
+use std::time::{Duration, Instant, SystemTime};
 
 use daggy::petgraph::EdgeDirection;
-use daggy::Dag;
-use log::{debug, info};
+use log::{info, warn};
 use thiserror::Error;
 use uuid::Uuid;
 
+#[cfg(feature = "async")]
+mod asynchronous;
+mod export;
+mod graph;
+mod merge;
 #[macro_use]
 pub mod testing;
 
+#[cfg(feature = "async")]
+pub use async_trait::async_trait;
+#[cfg(feature = "async")]
+pub use asynchronous::{AsyncAdapter, AsyncMigrator};
+use graph::{abort, Execution, IndexPlan, MigrationGraph};
+pub use merge::MergeMigration;
+
 /// Metadata for defining the identity and dependence relations of migrations.
 /// Specific adapters require additional traits for actual application and
 /// reversion of migrations.
@@ -33,6 +49,23 @@ pub trait Migration {
 
     /// User-targeted description of this migration.
     fn description(&self) -> &'static str;
+
+    /// Checksum of the content of this migration, if any. Adapters store this
+    /// when the migration is applied so that later edits to an applied
+    /// migration can be detected.
+    fn checksum(&self) -> Option<String> {
+        None
+    }
+
+    /// Whether this migration can be reverted. `Migrator::down` refuses to
+    /// revert anything if it would need to revert a migration that is not.
+    ///
+    /// Adapters' migration traits revert migrations with a `down` method that
+    /// does nothing by default, so migrations that can not be reverted should
+    /// return `false` here rather than rely on that default.
+    fn reversible(&self) -> bool {
+        true
+    }
 }
 
 /// Create a trivial implementation of `Migration` for a type.
@@ -91,7 +124,7 @@ macro_rules! migration {
 }
 
 /// Direction in which a migration is applied (`Up`) or reverted (`Down`).
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum MigrationDirection {
     Up,
     Down,
@@ -107,6 +140,137 @@ impl Display for MigrationDirection {
     }
 }
 
+/// A migration that `Migrator` would apply or revert, as returned by
+/// [`Migrator::plan_up`] and [`Migrator::plan_down`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PlannedMigration {
+    pub id: Uuid,
+    pub description: &'static str,
+    pub direction: MigrationDirection,
+}
+
+/// A migration that `Migrator` applied or reverted, as listed in a
+/// [`MigrationReport`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExecutedMigration {
+    pub id: Uuid,
+    pub description: &'static str,
+    pub direction: MigrationDirection,
+    /// Wall-clock time taken by the adapter to apply or revert the migration.
+    pub duration: Duration,
+}
+
+/// Result of [`Migrator::up`] or [`Migrator::down`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MigrationReport {
+    pub direction: MigrationDirection,
+    /// Migrations that were applied or reverted, in order.
+    pub executed: Vec<ExecutedMigration>,
+    /// Migrations in the target that were skipped because they were already
+    /// applied (when migrating up) or not applied (when migrating down), in
+    /// the order they would otherwise have been executed.
+    pub skipped: Vec<Uuid>,
+}
+
+/// Receiver of events from `Migrator::up` and `Migrator::down`, for example
+/// for progress reporting, audit logging or metrics. All methods default to
+/// doing nothing.
+pub trait MigrationObserver {
+    /// Called with all migrations about to be applied or reverted, before any
+    /// of them are.
+    fn on_plan(&mut self, _plan: &[PlannedMigration]) {}
+
+    /// Called before a migration is applied or reverted.
+    fn before_migration(&mut self, _migration: &PlannedMigration) {}
+
+    /// Called after a migration was successfully applied or reverted.
+    fn after_migration(&mut self, _migration: &ExecutedMigration) {}
+
+    /// Called when `up` or `down` failed, with the migration that failed to
+    /// be applied or reverted. The migration is `None` if the run failed
+    /// before any migration was executed, for example because the adapter's
+    /// lock could not be acquired or a migration to revert is irreversible.
+    /// No further migrations are executed.
+    fn on_error(&mut self, _migration: Option<&PlannedMigration>, _error: &dyn std::error::Error) {}
+
+    /// Called after all planned migrations were applied or reverted.
+    fn on_complete(&mut self, _report: &MigrationReport) {}
+}
+
+/// Classification of migrations by their state in the adapter, as returned by
+/// [`Migrator::status`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MigrationStatus {
+    /// Registered migrations that have been applied, in dependency order.
+    pub applied: Vec<Uuid>,
+    /// Registered migrations that have not been applied, in dependency order.
+    pub pending: Vec<Uuid>,
+    /// Migrations the adapter reports as applied but which are not registered.
+    pub orphaned: Vec<Uuid>,
+}
+
+/// An applied migration whose stored checksum differs from its current one,
+/// as returned by [`Migrator::checksum_mismatches`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChecksumMismatch {
+    pub id: Uuid,
+    /// Checksum stored by the adapter when the migration was applied.
+    pub stored: String,
+    /// Checksum of the migration as currently registered.
+    pub current: Option<String>,
+}
+
+/// Metadata an adapter stores about an applied migration, as returned by
+/// [`Migrator::applied_records`]. Fields are `None` where the adapter does not
+/// store them, or did not when the migration was applied.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AppliedMigration {
+    pub id: Uuid,
+    /// Description of the migration when it was applied.
+    pub description: Option<String>,
+    /// Checksum of the migration when it was applied.
+    pub checksum: Option<String>,
+    /// When the migration was applied or marked applied.
+    pub applied_at: Option<SystemTime>,
+    /// How long applying the migration took. This is `None` for migrations
+    /// marked applied without being applied.
+    pub duration: Option<Duration>,
+    /// Who applied the migration, such as an application version or host
+    /// name, as configured on the adapter.
+    pub applied_by: Option<String>,
+}
+
+/// An attempt to apply or revert a migration, or to mark it applied or
+/// reverted, as recorded by an adapter and returned by [`Migrator::history`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MigrationEvent {
+    pub id: Uuid,
+    pub direction: MigrationDirection,
+    /// Whether the migration was only marked applied or reverted rather than
+    /// executed.
+    pub marked: bool,
+    pub occurred_at: SystemTime,
+    /// The error if the attempt failed, or `None` if it succeeded.
+    pub error: Option<String>,
+    /// Who made the attempt, as configured on the adapter.
+    pub performed_by: Option<String>,
+}
+
+impl AppliedMigration {
+    /// Construct a record of an applied migration with no metadata besides
+    /// its ID.
+    pub fn new(id: Uuid) -> AppliedMigration {
+        AppliedMigration {
+            id,
+            description: None,
+            checksum: None,
+            applied_at: None,
+            duration: None,
+            applied_by: None,
+        }
+    }
+}
+
 /// Trait necessary to adapt schemer's migration management to a stateful
 /// backend.
 pub trait Adapter {
@@ -124,6 +288,76 @@ pub trait Adapter {
 
     /// Revert a single migration.
     fn revert_migration(&mut self, _: &Self::MigrationType) -> Result<(), Self::Error>;
+
+    /// Record a single migration as applied without applying it.
+    fn record_migration(&mut self, _: &Self::MigrationType) -> Result<(), Self::Error>;
+
+    /// Record a single migration as not applied without reverting it.
+    fn unrecord_migration(&mut self, _: &Self::MigrationType) -> Result<(), Self::Error>;
+
+    /// Returns the checksums stored for applied migrations that had one when
+    /// they were applied. Adapters that do not store checksums need not
+    /// implement this.
+    fn applied_checksums(&mut self) -> Result<HashMap<Uuid, String>, Self::Error> {
+        Ok(HashMap::new())
+    }
+
+    /// Returns a record of each applied migration with the metadata stored
+    /// about it, ordered by when they were applied where the adapter stores
+    /// this. Adapters storing no more than `applied_checksums` need not
+    /// implement this.
+    fn applied_records(&mut self) -> Result<Vec<AppliedMigration>, Self::Error> {
+        let mut checksums = self.applied_checksums()?;
+        let mut ids: Vec<Uuid> = self.applied_migrations()?.into_iter().collect();
+        ids.sort();
+        Ok(ids
+            .into_iter()
+            .map(|id| AppliedMigration {
+                checksum: checksums.remove(&id),
+                ..AppliedMigration::new(id)
+            })
+            .collect())
+    }
+
+    /// Returns every attempt to apply, revert, or mark a migration, including
+    /// those that failed, in the order they were made. Adapters that do not
+    /// keep a history need not implement this.
+    fn history(&mut self) -> Result<Vec<MigrationEvent>, Self::Error> {
+        Ok(vec![])
+    }
+
+    /// Acquire a lock excluding other migrators using the same database,
+    /// blocking until it is available. `Migrator` holds this lock for the
+    /// whole of `up` and `down`, and reads the applied migrations only once it
+    /// is held, so that concurrent migrators apply each migration once.
+    ///
+    /// Adapters that do not support locking need not implement this.
+    fn lock(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Release the lock acquired by `lock`. This is called even if migrating
+    /// failed.
+    fn unlock(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// Combine the result of a migration run with that of releasing the adapter
+/// lock held for it. An error from the run takes precedence, in which case an
+/// error releasing the lock is only logged.
+fn release_lock<R, E: std::error::Error + 'static>(
+    result: Result<R, MigratorError<E>>,
+    unlocked: Result<(), E>,
+) -> Result<R, MigratorError<E>> {
+    match (result, unlocked) {
+        (Err(e), Err(unlock_error)) => {
+            warn!("Failed to release adapter lock: {}", unlock_error);
+            Err(e)
+        }
+        (result, Ok(())) => result,
+        (Ok(_), Err(unlock_error)) => Err(MigratorError::Adapter(unlock_error)),
+    }
 }
 
 /// Error resulting from the definition of migration identity and dependency.
@@ -135,6 +369,18 @@ pub enum DependencyError {
     UnknownId(Uuid),
     #[error("Cyclic dependency caused by edge from migration IDs {from} to {to}")]
     Cycle { from: Uuid, to: Uuid },
+    #[error("Applied migration ID {0} is not registered")]
+    UnknownApplied(Uuid),
+    #[error("Applied migration ID {id} depends on unapplied migration ID {dependency}")]
+    UnappliedDependency { id: Uuid, dependency: Uuid },
+    #[error("Applied migration ID {id} has changed: stored checksum {stored}, current checksum {current:?}")]
+    ChecksumMismatch {
+        id: Uuid,
+        stored: String,
+        current: Option<String>,
+    },
+    #[error("Multiple head migrations: {0:?}")]
+    MultipleHeads(Vec<Uuid>),
 }
 
 /// Error resulting either from migration definitions or from migration
@@ -155,13 +401,17 @@ pub enum MigratorError<T: std::error::Error + 'static> {
         #[source]
         error: T,
     },
+    #[error("Migration {id} ({description}) is irreversible and cannot be reverted.")]
+    Irreversible { id: Uuid, description: &'static str },
 }
 
 /// Primary schemer type for defining and applying migrations.
 pub struct Migrator<T: Adapter> {
     adapter: T,
-    dependencies: Dag<Box<T::MigrationType>, ()>,
-    id_map: HashMap<Uuid, daggy::NodeIndex>,
+    graph: MigrationGraph<T::MigrationType>,
+    strict: bool,
+    single_head: bool,
+    observers: Vec<Box<dyn MigrationObserver + Send>>,
 }
 
 impl<T: Adapter> Migrator<T> {
@@ -169,150 +419,267 @@ impl<T: Adapter> Migrator<T> {
     pub fn new(adapter: T) -> Migrator<T> {
         Migrator {
             adapter,
-            dependencies: Dag::new(),
-            id_map: HashMap::new(),
+            graph: MigrationGraph::new(),
+            strict: false,
+            single_head: false,
+            observers: vec![],
         }
     }
 
+    /// Add an observer to be notified of events while migrating up or down.
+    /// Observers are notified in the order they were added.
+    pub fn add_observer(&mut self, observer: Box<dyn MigrationObserver + Send>) {
+        self.observers.push(observer);
+    }
+
+    /// Set whether `up`, `down` and their plans should fail if the applied
+    /// migrations are inconsistent with the dependency graph or have changed,
+    /// as checked by `validate`. Defaults to `false`, in which case orphaned
+    /// migrations are only logged.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    /// Set whether `up`, `down` and their plans should fail if the registered
+    /// migrations have more than one head, as checked by `check_single_head`.
+    /// Defaults to `false`.
+    pub fn set_single_head(&mut self, single_head: bool) {
+        self.single_head = single_head;
+    }
+
     /// Register a migration into the dependency graph.
     pub fn register(
         &mut self,
         migration: Box<T::MigrationType>,
     ) -> Result<(), MigratorError<T::Error>> {
-        let id = migration.id();
-        debug!("Registering migration {}", id);
-        if self.id_map.contains_key(&id) {
-            return Err(MigratorError::Dependency(DependencyError::DuplicateId(id)));
-        }
-        let depends = migration.dependencies();
-        let migration_idx = self.dependencies.add_node(migration);
-
-        for d in depends {
-            let parent_idx = self
-                .id_map
-                .get(&d)
-                .ok_or(MigratorError::Dependency(DependencyError::UnknownId(d)))?;
-            self.dependencies
-                .add_edge(*parent_idx, migration_idx, ())
-                .map_err(|_| {
-                    MigratorError::Dependency(DependencyError::Cycle { from: d, to: id })
-                })?;
-        }
-
-        self.id_map.insert(id, migration_idx);
-
-        Ok(())
+        self.register_multiple(vec![migration])
     }
 
     /// Register multiple migrations into the dependency graph. The `Vec` does
     /// not need to be order by dependency structure.
+    ///
+    /// Registration is atomic: if any migration can not be registered, none
+    /// are and the migrator is left unchanged.
     pub fn register_multiple(
         &mut self,
         migrations: Vec<Box<T::MigrationType>>,
     ) -> Result<(), MigratorError<T::Error>> {
-        for migration in migrations {
-            let id = migration.id();
-            debug!("Registering migration (with multiple) {}", id);
-            if self.id_map.contains_key(&id) {
-                return Err(MigratorError::Dependency(DependencyError::DuplicateId(id)));
-            }
-            let migration_idx = self.dependencies.add_node(migration);
-            self.id_map.insert(id, migration_idx);
+        self.graph
+            .register_multiple(migrations)
+            .map_err(MigratorError::Dependency)
+    }
+
+    /// All migrations that `id` depends on, directly or indirectly, in the
+    /// order `up` would apply them.
+    pub fn ancestors(&self, id: Uuid) -> Result<Vec<Uuid>, MigratorError<T::Error>> {
+        self.graph.ancestors(id).map_err(MigratorError::Dependency)
+    }
+
+    /// All migrations that depend on `id`, directly or indirectly, in the
+    /// order `up` would apply them. These are the migrations that must be
+    /// reverted to revert `id`.
+    pub fn descendants(&self, id: Uuid) -> Result<Vec<Uuid>, MigratorError<T::Error>> {
+        self.graph
+            .descendants(id)
+            .map_err(MigratorError::Dependency)
+    }
+
+    /// Migrations on which no other migrations depend (the sinks of the
+    /// dependency graph), in the order `up` would apply them.
+    pub fn heads(&self) -> Vec<Uuid> {
+        self.graph.externals(EdgeDirection::Outgoing)
+    }
+
+    /// Migrations which depend on no other migrations (the sources of the
+    /// dependency graph), in the order `up` would apply them.
+    pub fn roots(&self) -> Vec<Uuid> {
+        self.graph.externals(EdgeDirection::Incoming)
+    }
+
+    /// Direct dependencies of the migration `id`.
+    pub fn dependencies_of(&self, id: Uuid) -> Result<HashSet<Uuid>, MigratorError<T::Error>> {
+        self.graph
+            .dependencies_of(id)
+            .map_err(MigratorError::Dependency)
+    }
+
+    /// Check that the applied migrations are consistent with the dependency
+    /// graph and that none have changed since they were applied.
+    fn check_adapter(&mut self, applied: &HashSet<Uuid>) -> Result<(), MigratorError<T::Error>> {
+        let checksums = self.adapter.applied_checksums()?;
+        self.graph
+            .check_applied(applied, &checksums)
+            .map_err(MigratorError::Dependency)
+    }
+
+    /// Check that the registered migrations have at most one head, that is,
+    /// at most one migration on which no other migrations depend. A DAG with
+    /// a single head has one unambiguous target to migrate up to.
+    pub fn check_single_head(&self) -> Result<(), MigratorError<T::Error>> {
+        self.graph
+            .check_single_head()
+            .map_err(MigratorError::Dependency)
+    }
+
+    /// Read the applied migrations from the adapter, after checking the
+    /// single head policy if set. In strict mode the applied migrations must
+    /// pass `validate`, otherwise any that are not registered or have changed
+    /// are logged.
+    fn applied_migrations(&mut self) -> Result<HashSet<Uuid>, MigratorError<T::Error>> {
+        if self.single_head {
+            self.check_single_head()?;
         }
+        let applied_migrations = self.adapter.applied_migrations()?;
+        let checksums = self.adapter.applied_checksums()?;
+        self.graph
+            .inspect_applied(&applied_migrations, &checksums, self.strict)
+            .map_err(MigratorError::Dependency)?;
+        Ok(applied_migrations)
+    }
 
-        for (id, migration_idx) in &self.id_map {
-            let depends = self.dependencies[*migration_idx].dependencies();
-            for d in depends {
-                let parent_idx = self
-                    .id_map
-                    .get(&d)
-                    .ok_or(MigratorError::Dependency(DependencyError::UnknownId(d)))?;
-                self.dependencies
-                    .add_edge(*parent_idx, *migration_idx, ())
-                    .map_err(|_| {
-                        MigratorError::Dependency(DependencyError::Cycle { from: d, to: *id })
-                    })?;
-            }
+    /// Check that the applied migrations are consistent with the dependency
+    /// graph: every applied migration must be registered and have all of its
+    /// dependencies applied. Also check that no applied migration's checksum
+    /// has changed since it was applied.
+    pub fn validate(&mut self) -> Result<(), MigratorError<T::Error>> {
+        let applied_migrations = self.adapter.applied_migrations()?;
+        self.check_adapter(&applied_migrations)
+    }
+
+    /// List applied migrations whose checksum has changed since they were
+    /// applied. Migrations applied without a checksum are not compared.
+    pub fn checksum_mismatches(
+        &mut self,
+    ) -> Result<Vec<ChecksumMismatch>, MigratorError<T::Error>> {
+        let checksums = self.adapter.applied_checksums()?;
+        Ok(self.graph.mismatched(&checksums))
+    }
+
+    /// List the metadata the adapter stores about each applied migration,
+    /// such as when and by whom it was applied.
+    pub fn applied_records(&mut self) -> Result<Vec<AppliedMigration>, MigratorError<T::Error>> {
+        Ok(self.adapter.applied_records()?)
+    }
+
+    /// List every attempt the adapter has recorded to apply, revert, or mark
+    /// a migration, including those that failed, in the order they were made.
+    pub fn history(&mut self) -> Result<Vec<MigrationEvent>, MigratorError<T::Error>> {
+        Ok(self.adapter.history()?)
+    }
+
+    /// List, in order, the migrations that `up` would apply for the same
+    /// target without applying them.
+    pub fn plan_up(
+        &mut self,
+        to: Option<Uuid>,
+    ) -> Result<Vec<PlannedMigration>, MigratorError<T::Error>> {
+        let (indices, _) = self.up_indices(to)?;
+        Ok(self.graph.planned(&indices, MigrationDirection::Up))
+    }
+
+    /// List, in order, the migrations that `down` would revert for the same
+    /// target without reverting them. Like `down`, this fails if any of them
+    /// is irreversible.
+    pub fn plan_down(
+        &mut self,
+        to: Option<Uuid>,
+    ) -> Result<Vec<PlannedMigration>, MigratorError<T::Error>> {
+        let (indices, _) = self.down_indices(to)?;
+        Ok(self.graph.planned(&indices, MigrationDirection::Down))
+    }
+
+    /// Report which registered migrations are applied or pending, and which
+    /// applied migrations are orphaned (not registered).
+    pub fn status(&mut self) -> Result<MigrationStatus, MigratorError<T::Error>> {
+        let applied_migrations = self.adapter.applied_migrations()?;
+        Ok(self.graph.status(&applied_migrations))
+    }
+
+    /// Record the migrations `ids` as applied without applying them, for
+    /// example to adopt schemer for a database whose schema already matches
+    /// these migrations. Migrations already recorded as applied are skipped.
+    ///
+    /// The applied migrations are not validated, so this can also be used to
+    /// repair inconsistent metadata. Like `up`, the adapter's lock is held
+    /// throughout.
+    pub fn mark_applied(&mut self, ids: &[Uuid]) -> Result<(), MigratorError<T::Error>> {
+        self.adapter.lock()?;
+        let result = self.mark_applied_locked(ids);
+        release_lock(result, self.adapter.unlock())
+    }
+
+    fn mark_applied_locked(&mut self, ids: &[Uuid]) -> Result<(), MigratorError<T::Error>> {
+        let applied_migrations = self.adapter.applied_migrations()?;
+        let indices = self
+            .graph
+            .marked_indices(ids, &applied_migrations, MigrationDirection::Up)
+            .map_err(MigratorError::Dependency)?;
+        for idx in indices {
+            let migration = &self.graph.dependencies[idx];
+            info!("Marking migration {} as applied", migration.id());
+            self.adapter.record_migration(migration)?;
         }
 
         Ok(())
     }
 
-    /// Collect the ids of recursively dependent migrations in `dir` induced
-    /// starting from `id`. If `dir` is `Incoming`, this is all ancestors
-    /// (dependencies); if `Outgoing`, this is all descendents (dependents).
-    /// If `id` is `None`, this is all migrations starting from the sources or
-    /// the sinks, respectively.
-    fn induced_stream(
-        &self,
-        id: Option<Uuid>,
-        dir: EdgeDirection,
-    ) -> Result<HashSet<Uuid>, DependencyError> {
-        let mut target_ids = HashSet::new();
-        match id {
-            Some(id) => {
-                if !self.id_map.contains_key(&id) {
-                    return Err(DependencyError::UnknownId(id));
-                }
-                target_ids.insert(id);
-            }
-            // This will eventually yield all migrations, so could be optimized.
-            None => target_ids.extend(
-                self.dependencies
-                    .graph()
-                    .externals(dir.opposite())
-                    .map(|idx| self.dependencies[idx].id()),
-            ),
-        }
+    /// Record the migrations `ids` as not applied without reverting them.
+    /// Migrations not recorded as applied are skipped.
+    ///
+    /// The applied migrations are not validated, so this can also be used to
+    /// repair inconsistent metadata. Like `up`, the adapter's lock is held
+    /// throughout.
+    pub fn mark_reverted(&mut self, ids: &[Uuid]) -> Result<(), MigratorError<T::Error>> {
+        self.adapter.lock()?;
+        let result = self.mark_reverted_locked(ids);
+        release_lock(result, self.adapter.unlock())
+    }
 
-        let mut to_visit: VecDeque<_> = target_ids
-            .iter()
-            .map(|id| *self.id_map.get(id).expect("ID map is malformed"))
-            .collect();
-        while !to_visit.is_empty() {
-            let idx = to_visit.pop_front().expect("Impossible: not empty");
-            let id = self.dependencies[idx].id();
-            target_ids.insert(id);
-            to_visit.extend(self.dependencies.graph().neighbors_directed(idx, dir));
+    fn mark_reverted_locked(&mut self, ids: &[Uuid]) -> Result<(), MigratorError<T::Error>> {
+        let applied_migrations = self.adapter.applied_migrations()?;
+        let indices = self
+            .graph
+            .marked_indices(ids, &applied_migrations, MigrationDirection::Down)
+            .map_err(MigratorError::Dependency)?;
+        for idx in indices {
+            let migration = &self.graph.dependencies[idx];
+            info!("Marking migration {} as reverted", migration.id());
+            self.adapter.unrecord_migration(migration)?;
         }
 
-        Ok(target_ids)
+        Ok(())
     }
 
     /// Apply migrations as necessary to so that the specified migration is
     /// applied (inclusive).
     ///
     /// If `to` is `None`, apply all registered migrations.
-    pub fn up(&mut self, to: Option<Uuid>) -> Result<(), MigratorError<T::Error>> {
+    ///
+    /// Returns a report of the migrations applied and of those skipped because
+    /// they were already applied.
+    ///
+    /// Migrations are applied after all of their dependencies. Whenever
+    /// several migrations have all of their dependencies applied, the one
+    /// registered earliest is applied next, so the same registrations always
+    /// yield the same order.
+    ///
+    /// The adapter's lock is held throughout, so concurrent migrators using
+    /// the same database wait for each other rather than racing.
+    pub fn up(&mut self, to: Option<Uuid>) -> Result<MigrationReport, MigratorError<T::Error>> {
         info!("Migrating up to target: {:?}", to);
-        let target_ids = self
-            .induced_stream(to, EdgeDirection::Incoming)
-            .map_err(MigratorError::Dependency)?;
-
-        // TODO: This is assuming the applied_migrations state is consistent
-        // with the dependency graph.
-        let applied_migrations = self.adapter.applied_migrations()?;
-        for idx in &daggy::petgraph::algo::toposort(self.dependencies.graph(), None)
-            .expect("Impossible: dependencies are a DAG")
-        {
-            let migration = &self.dependencies[*idx];
-            let id = migration.id();
-            if applied_migrations.contains(&id) || !target_ids.contains(&id) {
-                continue;
-            }
-
-            info!("Applying migration {}", id);
-            self.adapter
-                .apply_migration(migration)
-                .map_err(|e| MigratorError::Migration {
-                    id,
-                    description: migration.description(),
-                    direction: MigrationDirection::Up,
-                    error: e,
-                })?;
+        if let Err(e) = self.adapter.lock() {
+            return abort(&mut self.observers, MigratorError::Adapter(e));
         }
+        let result = self.up_locked(to);
+        release_lock(result, self.adapter.unlock())
+    }
 
-        Ok(())
+    fn up_locked(&mut self, to: Option<Uuid>) -> Result<MigrationReport, MigratorError<T::Error>> {
+        let (indices, skipped) = match self.up_indices(to) {
+            Ok(indices) => indices,
+            Err(e) => return abort(&mut self.observers, e),
+        };
+        self.execute(indices, skipped, MigrationDirection::Up)
     }
 
     /// Revert migrations as necessary so that no migrations dependent on the
@@ -320,39 +687,89 @@ impl<T: Adapter> Migrator<T> {
     /// applied, it will still be applied.
     ///
     /// If `to` is `None`, revert all applied migrations.
-    pub fn down(&mut self, to: Option<Uuid>) -> Result<(), MigratorError<T::Error>> {
+    ///
+    /// Returns a report of the migrations reverted and of those skipped because
+    /// they were not applied.
+    ///
+    /// Migrations are reverted in exactly the reverse of the order in which
+    /// `up` would apply them. If any migration to be reverted is irreversible,
+    /// no migrations are reverted.
+    ///
+    /// Like `up`, the adapter's lock is held throughout.
+    pub fn down(&mut self, to: Option<Uuid>) -> Result<MigrationReport, MigratorError<T::Error>> {
         info!("Migrating down to target: {:?}", to);
-        let mut target_ids = self
-            .induced_stream(to, EdgeDirection::Outgoing)
-            .map_err(MigratorError::Dependency)?;
-        if let Some(sink_id) = to {
-            target_ids.remove(&sink_id);
+        if let Err(e) = self.adapter.lock() {
+            return abort(&mut self.observers, MigratorError::Adapter(e));
         }
+        let result = self.down_locked(to);
+        release_lock(result, self.adapter.unlock())
+    }
 
-        let applied_migrations = self.adapter.applied_migrations()?;
-        for idx in daggy::petgraph::algo::toposort(self.dependencies.graph(), None)
-            .expect("Impossible: dependencies are a DAG")
-            .iter()
-            .rev()
-        {
-            let migration = &self.dependencies[*idx];
-            let id = migration.id();
-            if !applied_migrations.contains(&id) || !target_ids.contains(&id) {
-                continue;
-            }
+    fn down_locked(
+        &mut self,
+        to: Option<Uuid>,
+    ) -> Result<MigrationReport, MigratorError<T::Error>> {
+        let (indices, skipped) = match self.down_indices(to) {
+            Ok(indices) => indices,
+            Err(e) => return abort(&mut self.observers, e),
+        };
+        self.execute(indices, skipped, MigrationDirection::Down)
+    }
+
+    /// Indices of the migrations that `up` would apply for `to`, and IDs of
+    /// those it would skip because they are already applied.
+    fn up_indices(&mut self, to: Option<Uuid>) -> Result<IndexPlan, MigratorError<T::Error>> {
+        let applied_migrations = self.applied_migrations()?;
+        self.graph
+            .up_indices(to, &applied_migrations)
+            .map_err(MigratorError::Dependency)
+    }
 
-            info!("Reverting migration {}", id);
-            self.adapter
-                .revert_migration(migration)
-                .map_err(|e| MigratorError::Migration {
-                    id,
-                    description: migration.description(),
-                    direction: MigrationDirection::Down,
-                    error: e,
-                })?;
+    /// Indices of the migrations that `down` would revert for `to`, and IDs
+    /// of those it would skip because they are not applied. Fails if any of
+    /// them is irreversible.
+    fn down_indices(&mut self, to: Option<Uuid>) -> Result<IndexPlan, MigratorError<T::Error>> {
+        let applied_migrations = self.applied_migrations()?;
+        let (indices, skipped) = self
+            .graph
+            .down_indices(to, &applied_migrations)
+            .map_err(MigratorError::Dependency)?;
+        self.graph.check_reversible(&indices)?;
+        Ok((indices, skipped))
+    }
+
+    /// Apply or revert the migrations at `indices` in order.
+    fn execute(
+        &mut self,
+        indices: Vec<daggy::NodeIndex>,
+        skipped: Vec<Uuid>,
+        direction: MigrationDirection,
+    ) -> Result<MigrationReport, MigratorError<T::Error>> {
+        let plan = self.graph.planned(&indices, direction);
+        let mut execution = Execution::start(&mut self.observers, &plan, direction, skipped);
+
+        for (idx, planned) in indices.into_iter().zip(plan) {
+            let migration = &self.graph.dependencies[idx];
+            execution.before(&planned);
+
+            let start = Instant::now();
+            let result = match direction {
+                MigrationDirection::Up => {
+                    info!("Applying migration {}", planned.id);
+                    self.adapter.apply_migration(migration)
+                }
+                MigrationDirection::Down => {
+                    info!("Reverting migration {}", planned.id);
+                    self.adapter.revert_migration(migration)
+                }
+            };
+            match result {
+                Ok(()) => execution.succeeded(planned, start.elapsed()),
+                Err(e) => return Err(execution.failed(planned, e)),
+            }
         }
 
-        Ok(())
+        Ok(execution.finish())
     }
 }
 
@@ -362,13 +779,17 @@ pub mod tests {
     use super::*;
 
     struct DefaultTestAdapter {
-        applied_migrations: HashSet<Uuid>,
+        applied_migrations: HashMap<Uuid, Option<String>>,
+        locked: bool,
+        locks: usize,
     }
 
     impl DefaultTestAdapter {
         fn new() -> DefaultTestAdapter {
             DefaultTestAdapter {
-                applied_migrations: HashSet::new(),
+                applied_migrations: HashMap::new(),
+                locked: false,
+                locks: 0,
             }
         }
     }
@@ -383,11 +804,12 @@ pub mod tests {
         type Error = DefaultTestAdapterError;
 
         fn applied_migrations(&mut self) -> Result<HashSet<Uuid>, Self::Error> {
-            Ok(self.applied_migrations.clone())
+            Ok(self.applied_migrations.keys().cloned().collect())
         }
 
         fn apply_migration(&mut self, migration: &Self::MigrationType) -> Result<(), Self::Error> {
-            self.applied_migrations.insert(migration.id());
+            self.applied_migrations
+                .insert(migration.id(), migration.checksum());
             Ok(())
         }
 
@@ -395,13 +817,464 @@ pub mod tests {
             self.applied_migrations.remove(&migration.id());
             Ok(())
         }
+
+        fn record_migration(&mut self, migration: &Self::MigrationType) -> Result<(), Self::Error> {
+            assert!(self.locked, "Migration marked without adapter lock");
+            self.apply_migration(migration)
+        }
+
+        fn unrecord_migration(
+            &mut self,
+            migration: &Self::MigrationType,
+        ) -> Result<(), Self::Error> {
+            assert!(self.locked, "Migration marked without adapter lock");
+            self.revert_migration(migration)
+        }
+
+        fn applied_checksums(&mut self) -> Result<HashMap<Uuid, String>, Self::Error> {
+            Ok(self
+                .applied_migrations
+                .iter()
+                .filter_map(|(id, checksum)| checksum.clone().map(|c| (*id, c)))
+                .collect())
+        }
+
+        fn lock(&mut self) -> Result<(), Self::Error> {
+            assert!(!self.locked, "Adapter lock acquired while already held");
+            self.locked = true;
+            self.locks += 1;
+            Ok(())
+        }
+
+        fn unlock(&mut self) -> Result<(), Self::Error> {
+            assert!(self.locked, "Adapter lock released while not held");
+            self.locked = false;
+            Ok(())
+        }
     }
 
     impl TestAdapter for DefaultTestAdapter {
-        fn mock(id: Uuid, dependencies: HashSet<Uuid>) -> Box<Self::MigrationType> {
-            Box::new(TestMigration::new(id, dependencies))
+        fn mock_from(migration: TestMigration) -> Box<Self::MigrationType> {
+            Box::new(migration)
         }
     }
 
     test_schemer_adapter!(DefaultTestAdapter::new());
+
+    #[test]
+    fn test_lock_held_while_migrating() {
+        let mut migrator = Migrator::new(DefaultTestAdapter::new());
+        let id = Uuid::parse_str("bc960dc8-0e4a-4182-a62a-8e776d1e2b30").unwrap();
+        migrator
+            .register(DefaultTestAdapter::mock(id, HashSet::new()))
+            .unwrap();
+
+        migrator.up(None).unwrap();
+        assert!(!migrator.adapter.locked);
+        assert_eq!(migrator.adapter.locks, 1);
+
+        // The lock is released even if migrating fails.
+        let unknown = Uuid::parse_str("0f5ec4a1-1e35-4bd2-9b4e-2b6f8c4c8a5b").unwrap();
+        assert!(migrator.down(Some(unknown)).is_err());
+        assert!(!migrator.adapter.locked);
+        assert_eq!(migrator.adapter.locks, 2);
+
+        migrator.mark_reverted(&[id]).unwrap();
+        migrator.mark_applied(&[id]).unwrap();
+        assert!(!migrator.adapter.locked);
+        assert_eq!(migrator.adapter.locks, 4);
+    }
+
+    /// IDs for the mock migrations in the graph tests below.
+    fn ids() -> [Uuid; 5] {
+        [
+            "bc960dc8-0e4a-4182-a62a-8e776d1e2b30",
+            "4885e8ab-dafa-4d76-a565-2dee8b04ef60",
+            "c5d07448-851f-45e8-8fa7-4823d5250609",
+            "9433a432-386f-467e-a59f-a9fb7e249767",
+            "0940acb1-0e2e-4b99-9d69-2302a9c74524",
+        ]
+        .map(|id| Uuid::parse_str(id).unwrap())
+    }
+
+    /// Build a migrator with a mock migration registered for each ID with
+    /// the given dependencies, in order.
+    fn mock_migrator(migrations: &[(Uuid, &[Uuid])]) -> Migrator<DefaultTestAdapter> {
+        let mut migrator = Migrator::new(DefaultTestAdapter::new());
+        migrator
+            .register_multiple(
+                migrations
+                    .iter()
+                    .map(|(id, dependencies)| {
+                        DefaultTestAdapter::mock(*id, dependencies.iter().cloned().collect())
+                    })
+                    .collect(),
+            )
+            .expect("Migration registration failed");
+        migrator
+    }
+
+    /// Of the migrations whose dependencies are applied, the earliest
+    /// registered is applied next, and reverting follows the reverse order.
+    #[test]
+    fn test_registration_order() {
+        let [uuid1, uuid2, uuid3, uuid4, _] = ids();
+        let mut migrator = mock_migrator(&[
+            (uuid4, &[uuid2]),
+            (uuid3, &[uuid1]),
+            (uuid2, &[]),
+            (uuid1, &[]),
+        ]);
+
+        let plan = migrator.plan_up(None).expect("Up plan failed");
+        assert_eq!(
+            plan.iter().map(|p| p.id).collect::<Vec<_>>(),
+            vec![uuid2, uuid4, uuid1, uuid3]
+        );
+
+        migrator.up(None).expect("Up migration failed");
+
+        let plan = migrator.plan_down(None).expect("Down plan failed");
+        assert_eq!(
+            plan.iter().map(|p| p.id).collect::<Vec<_>>(),
+            vec![uuid3, uuid1, uuid4, uuid2]
+        );
+    }
+
+    /// Failed registrations leave the migrator unchanged and registering in
+    /// several batches works.
+    #[test]
+    fn test_register_atomic() {
+        let [uuid1, uuid2, uuid3, uuid4, unknown] = ids();
+        let mut migrator = mock_migrator(&[(uuid1, &[])]);
+
+        let assert_unchanged = |migrator: &Migrator<DefaultTestAdapter>| {
+            assert_eq!(migrator.graph.id_map.len(), 1);
+            assert_eq!(migrator.graph.dependencies.node_count(), 1);
+            assert_eq!(migrator.graph.dependencies.edge_count(), 0);
+        };
+        let mock = DefaultTestAdapter::mock;
+
+        match migrator.register_multiple(vec![
+            mock(uuid2, vec![uuid1].into_iter().collect()),
+            mock(uuid3, vec![unknown].into_iter().collect()),
+        ]) {
+            Err(MigratorError::Dependency(DependencyError::UnknownId(id))) => {
+                assert_eq!(id, unknown)
+            }
+            _ => panic!("Registration did not detect unknown dependency"),
+        }
+        assert_unchanged(&migrator);
+
+        match migrator.register_multiple(vec![
+            mock(uuid2, vec![uuid1].into_iter().collect()),
+            mock(uuid1, HashSet::new()),
+        ]) {
+            Err(MigratorError::Dependency(DependencyError::DuplicateId(id))) => {
+                assert_eq!(id, uuid1)
+            }
+            _ => panic!("Registration did not detect duplicate ID"),
+        }
+        assert_unchanged(&migrator);
+
+        match migrator.register_multiple(vec![
+            mock(uuid2, vec![uuid1, uuid3].into_iter().collect()),
+            mock(uuid3, vec![uuid2].into_iter().collect()),
+        ]) {
+            Err(MigratorError::Dependency(DependencyError::Cycle { .. })) => {}
+            _ => panic!("Registration did not detect cycle"),
+        }
+        assert_unchanged(&migrator);
+
+        migrator
+            .register_multiple(vec![
+                mock(uuid3, vec![uuid2].into_iter().collect()),
+                mock(uuid2, vec![uuid1].into_iter().collect()),
+            ])
+            .expect("Migration registration failed");
+        migrator
+            .register_multiple(vec![mock(uuid4, vec![uuid3].into_iter().collect())])
+            .expect("Migration registration failed");
+        assert_eq!(migrator.graph.dependencies.node_count(), 4);
+        assert_eq!(migrator.graph.dependencies.edge_count(), 3);
+
+        migrator.up(None).expect("Up migration failed");
+        assert_eq!(migrator.adapter.applied_migrations.len(), 4);
+    }
+
+    /// Rendering the dependency graph as DOT with and without state.
+    #[test]
+    fn test_dot() {
+        let [uuid1, uuid2, uuid3, _, _] = ids();
+        let mut migrator = mock_migrator(&[(uuid1, &[]), (uuid2, &[]), (uuid3, &[uuid1, uuid2])]);
+
+        assert_eq!(
+            migrator.to_dot(),
+            format!(
+                "digraph migrations {{\n    \
+                    node [shape=box];\n    \
+                    \"{0}\" [label=\"Test Migration\\nbc960dc8\"];\n    \
+                    \"{1}\" [label=\"Test Migration\\n4885e8ab\"];\n    \
+                    \"{2}\" [label=\"Test Migration\\nc5d07448\"];\n    \
+                    \"{0}\" -> \"{2}\";\n    \
+                    \"{1}\" -> \"{2}\";\n\
+                }}\n",
+                uuid1, uuid2, uuid3
+            )
+        );
+
+        migrator.up(Some(uuid1)).expect("Up migration failed");
+
+        let dot = migrator.to_dot_with_state().expect("Rendering failed");
+        assert!(dot.contains(&format!(
+            "\"{}\" [label=\"Test Migration\\nbc960dc8\", style=filled, fillcolor=palegreen];",
+            uuid1
+        )));
+        assert!(dot.contains(&format!(
+            "\"{}\" [label=\"Test Migration\\n4885e8ab\", style=filled, fillcolor=lightgoldenrod1];",
+            uuid2
+        )));
+    }
+
+    /// Rendering the whole and partial dependency graph as Mermaid.
+    #[test]
+    fn test_mermaid() {
+        let [uuid1, uuid2, uuid3, uuid4, unknown] = ids();
+        let migrator = mock_migrator(&[
+            (uuid1, &[]),
+            (uuid2, &[]),
+            (uuid3, &[uuid1, uuid2]),
+            (uuid4, &[uuid1]),
+        ]);
+
+        assert_eq!(
+            migrator.to_mermaid(None).expect("Rendering failed"),
+            "graph TD\n    \
+                mbc960dc80e4a4182a62a8e776d1e2b30[\"Test Migration<br/>bc960dc8\"]\n    \
+                m4885e8abdafa4d76a5652dee8b04ef60[\"Test Migration<br/>4885e8ab\"]\n    \
+                mc5d07448851f45e88fa74823d5250609[\"Test Migration<br/>c5d07448\"]\n    \
+                m9433a432386f467ea59fa9fb7e249767[\"Test Migration<br/>9433a432\"]\n    \
+                mbc960dc80e4a4182a62a8e776d1e2b30 --> mc5d07448851f45e88fa74823d5250609\n    \
+                m4885e8abdafa4d76a5652dee8b04ef60 --> mc5d07448851f45e88fa74823d5250609\n    \
+                mbc960dc80e4a4182a62a8e776d1e2b30 --> m9433a432386f467ea59fa9fb7e249767\n"
+        );
+
+        assert_eq!(
+            migrator.to_mermaid(Some(uuid4)).expect("Rendering failed"),
+            "graph TD\n    \
+                mbc960dc80e4a4182a62a8e776d1e2b30[\"Test Migration<br/>bc960dc8\"]\n    \
+                m9433a432386f467ea59fa9fb7e249767[\"Test Migration<br/>9433a432\"]\n    \
+                mbc960dc80e4a4182a62a8e776d1e2b30 --> m9433a432386f467ea59fa9fb7e249767\n"
+        );
+
+        assert!(migrator.to_mermaid(Some(unknown)).is_err());
+    }
+
+    /// Querying the structure of a branching DAG.
+    #[test]
+    fn test_graph_queries() {
+        let [uuid1, uuid2, uuid3, uuid4, uuid5] = ids();
+        let migrator = mock_migrator(&[
+            (uuid1, &[]),
+            (uuid2, &[]),
+            (uuid3, &[uuid1, uuid2]),
+            (uuid4, &[uuid3]),
+            (uuid5, &[uuid3]),
+        ]);
+
+        assert_eq!(migrator.roots(), vec![uuid1, uuid2]);
+        assert_eq!(migrator.heads(), vec![uuid4, uuid5]);
+        assert_eq!(
+            migrator.ancestors(uuid4).unwrap(),
+            vec![uuid1, uuid2, uuid3]
+        );
+        assert!(migrator.ancestors(uuid1).unwrap().is_empty());
+        assert_eq!(
+            migrator.descendants(uuid2).unwrap(),
+            vec![uuid3, uuid4, uuid5]
+        );
+        assert!(migrator.descendants(uuid5).unwrap().is_empty());
+        assert_eq!(
+            migrator.dependencies_of(uuid3).unwrap(),
+            vec![uuid1, uuid2].into_iter().collect()
+        );
+
+        let unknown = Uuid::parse_str("6ae33a8e-3f1c-4e4e-9d3b-0d5a4bb7bd0c").unwrap();
+        assert!(migrator.ancestors(unknown).is_err());
+        assert!(migrator.descendants(unknown).is_err());
+        assert!(migrator.dependencies_of(unknown).is_err());
+    }
+
+    /// Detection of multiple heads and enforcement of the single head policy.
+    #[test]
+    fn test_single_head() {
+        let [uuid1, uuid2, uuid3, uuid4, _] = ids();
+        let mut migrator = mock_migrator(&[(uuid1, &[]), (uuid2, &[uuid1]), (uuid3, &[uuid1])]);
+        migrator.set_single_head(true);
+
+        match migrator.check_single_head() {
+            Err(MigratorError::Dependency(DependencyError::MultipleHeads(heads))) => {
+                assert_eq!(heads, vec![uuid2, uuid3]);
+            }
+            _ => panic!("Multiple heads were not detected"),
+        }
+        assert!(migrator.plan_up(None).is_err());
+        assert!(migrator.up(None).is_err());
+        assert!(migrator.adapter.applied_migrations.is_empty());
+
+        migrator
+            .register(DefaultTestAdapter::mock(
+                uuid4,
+                vec![uuid2, uuid3].into_iter().collect(),
+            ))
+            .expect("Migration registration failed");
+        migrator
+            .check_single_head()
+            .expect("Single head check failed");
+        migrator.up(None).expect("Up migration failed");
+        assert_eq!(migrator.adapter.applied_migrations.len(), 4);
+    }
+
+    /// Generating a merge migration for multiple heads.
+    #[test]
+    fn test_merge_heads() {
+        let [uuid1, uuid2, uuid3, _, _] = ids();
+        let mut migrator = mock_migrator(&[(uuid1, &[]), (uuid2, &[uuid1])]);
+        assert!(migrator.merge_heads().is_none());
+
+        migrator
+            .register(DefaultTestAdapter::mock(
+                uuid3,
+                vec![uuid1].into_iter().collect(),
+            ))
+            .expect("Migration registration failed");
+
+        let merge = migrator
+            .merge_heads()
+            .expect("No merge migration for heads");
+        assert_eq!(
+            merge.dependencies(),
+            vec![uuid2, uuid3].into_iter().collect()
+        );
+        assert_eq!(
+            merge.to_source("MergeMigration"),
+            format!(
+                "struct MergeMigration;\n\
+                migration!(\n    \
+                    MergeMigration,\n    \
+                    \"{}\",\n    \
+                    [\n        \
+                        \"{}\",\n        \
+                        \"{}\",\n    \
+                    ],\n    \
+                    \"Merge migration\");\n",
+                merge.id(),
+                uuid2,
+                uuid3
+            )
+        );
+
+        migrator
+            .register(DefaultTestAdapter::mock(merge.id(), merge.dependencies()))
+            .expect("Migration registration failed");
+        assert_eq!(migrator.heads(), vec![merge.id()]);
+        migrator
+            .check_single_head()
+            .expect("Single head check failed");
+    }
+
+    #[cfg(feature = "async")]
+    mod async_tests {
+        use super::*;
+
+        struct DefaultAsyncTestAdapter(DefaultTestAdapter);
+
+        #[async_trait]
+        impl AsyncAdapter for DefaultAsyncTestAdapter {
+            type MigrationType = dyn Migration + Send + Sync;
+
+            type Error = DefaultTestAdapterError;
+
+            async fn applied_migrations(&mut self) -> Result<HashSet<Uuid>, Self::Error> {
+                Adapter::applied_migrations(&mut self.0)
+            }
+
+            async fn apply_migration(
+                &mut self,
+                migration: &Self::MigrationType,
+            ) -> Result<(), Self::Error> {
+                self.0.apply_migration(migration)
+            }
+
+            async fn revert_migration(
+                &mut self,
+                migration: &Self::MigrationType,
+            ) -> Result<(), Self::Error> {
+                self.0.revert_migration(migration)
+            }
+
+            async fn record_migration(
+                &mut self,
+                migration: &Self::MigrationType,
+            ) -> Result<(), Self::Error> {
+                self.0.record_migration(migration)
+            }
+
+            async fn unrecord_migration(
+                &mut self,
+                migration: &Self::MigrationType,
+            ) -> Result<(), Self::Error> {
+                self.0.unrecord_migration(migration)
+            }
+
+            async fn applied_checksums(&mut self) -> Result<HashMap<Uuid, String>, Self::Error> {
+                Adapter::applied_checksums(&mut self.0)
+            }
+
+            async fn lock(&mut self) -> Result<(), Self::Error> {
+                Adapter::lock(&mut self.0)
+            }
+
+            async fn unlock(&mut self) -> Result<(), Self::Error> {
+                Adapter::unlock(&mut self.0)
+            }
+        }
+
+        impl AsyncTestAdapter for DefaultAsyncTestAdapter {
+            fn mock_from(migration: TestMigration) -> Box<Self::MigrationType> {
+                Box::new(migration)
+            }
+        }
+
+        test_schemer_async_adapter!(
+            futures_executor::block_on,
+            DefaultAsyncTestAdapter(DefaultTestAdapter::new())
+        );
+
+        #[test]
+        fn test_lock_held_while_migrating_async() {
+            futures_executor::block_on(async {
+                let mut migrator =
+                    AsyncMigrator::new(DefaultAsyncTestAdapter(DefaultTestAdapter::new()));
+                let [id, _, _, _, unknown] = ids();
+                migrator
+                    .register(DefaultAsyncTestAdapter::mock(id, HashSet::new()))
+                    .unwrap();
+
+                migrator.up(None).await.unwrap();
+                assert!(!migrator.adapter.0.locked);
+                assert_eq!(migrator.adapter.0.locks, 1);
+
+                // The lock is released even if migrating fails.
+                assert!(migrator.down(Some(unknown)).await.is_err());
+                assert!(!migrator.adapter.0.locked);
+                assert_eq!(migrator.adapter.0.locks, 2);
+
+                migrator.mark_reverted(&[id]).await.unwrap();
+                migrator.mark_applied(&[id]).await.unwrap();
+                assert!(!migrator.adapter.0.locked);
+                assert_eq!(migrator.adapter.0.locks, 4);
+            });
+        }
+    }
 }
@@ -0,0 +1,85 @@
+//! Generation of migrations merging multiple heads of the dependency graph.
+
+use std::collections::HashSet;
+use std::fmt::Write;
+
+use daggy::petgraph::EdgeDirection;
+use uuid::Uuid;
+
+use super::graph::MigrationGraph;
+use super::{Adapter, Migration, Migrator};
+
+/// A no-op migration depending on several migrations, usually the heads of a
+/// dependency graph, so that they can be migrated to as a single target.
+///
+/// This is intended to be turned into source code with `to_source`, so that
+/// the merge has a fixed ID once it is applied.
+#[derive(Clone, Debug)]
+pub struct MergeMigration {
+    id: Uuid,
+    dependencies: Vec<Uuid>,
+}
+
+impl MergeMigration {
+    /// Construct a merge migration with a new random ID depending on
+    /// `dependencies`.
+    pub fn new(dependencies: Vec<Uuid>) -> MergeMigration {
+        MergeMigration {
+            id: Uuid::new_v4(),
+            dependencies,
+        }
+    }
+
+    /// Rust source declaring this migration as a unit struct named `name`
+    /// implementing `Migration` via the `migration!` macro. Adapter-specific
+    /// migration traits, whose methods default to no-ops, must still be
+    /// implemented for the struct.
+    pub fn to_source(&self, name: &str) -> String {
+        let mut source = format!(
+            "struct {};\nmigration!(\n    {},\n    \"{}\",\n    [\n",
+            name, name, self.id
+        );
+        for dependency in &self.dependencies {
+            writeln!(source, "        \"{}\",", dependency)
+                .expect("Impossible: writing to a String");
+        }
+        writeln!(source, "    ],\n    \"{}\");", self.description())
+            .expect("Impossible: writing to a String");
+
+        source
+    }
+}
+
+impl Migration for MergeMigration {
+    fn id(&self) -> Uuid {
+        self.id
+    }
+
+    fn dependencies(&self) -> HashSet<Uuid> {
+        self.dependencies.iter().cloned().collect()
+    }
+
+    fn description(&self) -> &'static str {
+        "Merge migration"
+    }
+}
+
+impl<M: Migration + ?Sized> MigrationGraph<M> {
+    /// See `Migrator::merge_heads`.
+    pub(crate) fn merge_heads(&self) -> Option<MergeMigration> {
+        let heads = self.externals(EdgeDirection::Outgoing);
+        if heads.len() > 1 {
+            Some(MergeMigration::new(heads))
+        } else {
+            None
+        }
+    }
+}
+
+impl<T: Adapter> Migrator<T> {
+    /// Construct a merge migration depending on all current heads, if there
+    /// is more than one.
+    pub fn merge_heads(&self) -> Option<MergeMigration> {
+        self.graph.merge_heads()
+    }
+}
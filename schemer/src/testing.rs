@@ -1,15 +1,23 @@
 //! Test harness for applying a generic test suite to any backend-specific
 //! schemer adapter.
 
+use std::sync::{Arc, Mutex};
+
 use super::*;
 
 /// A trait required for running the generic test suite on an `Adapter`.
 pub trait TestAdapter: Adapter {
-    /// Construct a mock, no-op migration of the adapter's `MigrationType`.
+    /// Construct a mock, no-op migration of the adapter's `MigrationType`
+    /// from a `TestMigration`.
     ///
-    /// For convenience adapters can implement their migration traits on
-    /// `TestMigration` and construct those here.
-    fn mock(id: Uuid, dependencies: HashSet<Uuid>) -> Box<Self::MigrationType>;
+    /// Adapters should implement their migration traits on `TestMigration`
+    /// and box it here.
+    fn mock_from(migration: TestMigration) -> Box<Self::MigrationType>;
+
+    /// Construct a mock, no-op migration of the adapter's `MigrationType`.
+    fn mock(id: Uuid, dependencies: HashSet<Uuid>) -> Box<Self::MigrationType> {
+        Self::mock_from(TestMigration::new(id, dependencies))
+    }
 }
 
 /// A trivial struct implementing `Migration` on which adapters can build their
@@ -17,11 +25,30 @@ pub trait TestAdapter: Adapter {
 pub struct TestMigration {
     id: Uuid,
     dependencies: HashSet<Uuid>,
+    checksum: Option<String>,
+    reversible: bool,
 }
 
 impl TestMigration {
     pub fn new(id: Uuid, dependencies: HashSet<Uuid>) -> TestMigration {
-        TestMigration { id, dependencies }
+        TestMigration {
+            id,
+            dependencies,
+            checksum: None,
+            reversible: true,
+        }
+    }
+
+    /// Set the checksum this migration reports.
+    pub fn with_checksum(mut self, checksum: &str) -> TestMigration {
+        self.checksum = Some(checksum.to_owned());
+        self
+    }
+
+    /// Mark this migration as irreversible.
+    pub fn irreversible(mut self) -> TestMigration {
+        self.reversible = false;
+        self
     }
 }
 
@@ -37,6 +64,14 @@ impl Migration for TestMigration {
     fn description(&self) -> &'static str {
         "Test Migration"
     }
+
+    fn checksum(&self) -> Option<String> {
+        self.checksum.clone()
+    }
+
+    fn reversible(&self) -> bool {
+        self.reversible
+    }
 }
 
 /// Test an `Adapter` with the generic test suite.
@@ -45,6 +80,9 @@ impl Migration for TestMigration {
 /// should be done only for the testing configuration of the adapter's crate,
 /// as it is not necessary for normal behavior.
 ///
+/// An attribute before the setup statement, such as `#[ignore]`, is applied to
+/// each generated test.
+///
 /// # Examples
 ///
 /// ```rust,ignore
@@ -58,27 +96,58 @@ impl Migration for TestMigration {
 /// ```
 #[macro_export]
 macro_rules! test_schemer_adapter {
-    ($constructor:expr) => {
-        test_schemer_adapter!({}, $constructor);
+    (@tests $attrs:tt $setup:stmt, $constructor:expr, $($test_fn:ident),* $(,)*) => {
+        $(
+            test_schemer_adapter!(@test $attrs $setup, $constructor, $test_fn);
+        )*
     };
-    ($setup:stmt, $constructor:expr) => {
-        test_schemer_adapter!($setup, $constructor,
+    (@test [$($attr:tt)*] $setup:stmt, $constructor:expr, $test_fn:ident) => {
+        #[test]
+        $($attr)*
+        fn $test_fn() {
+            $setup
+            let adapter = $constructor;
+            $crate::testing::$test_fn(adapter);
+        }
+    };
+    (@suite $attrs:tt $setup:stmt, $constructor:expr) => {
+        test_schemer_adapter!(@tests $attrs $setup, $constructor,
             test_single_migration,
             test_migration_chain,
             test_multi_component_dag,
             test_branching_dag,
+            test_plan,
+            test_status,
+            test_validate,
+            test_checksum,
+            test_applied_records,
+            test_report,
+            test_observer,
+            test_irreversible,
+            test_mark,
         );
     };
+    (#[$attr:meta] $setup:stmt, $constructor:expr) => {
+        test_schemer_adapter!(@suite [#[$attr]] $setup, $constructor);
+    };
+    (#[$attr:meta] $setup:stmt, $constructor:expr, $($test_fn:ident),* $(,)*) => {
+        test_schemer_adapter!(@tests [#[$attr]] $setup, $constructor, $($test_fn),*);
+    };
+    ($constructor:expr) => {
+        test_schemer_adapter!({}, $constructor);
+    };
+    ($setup:stmt, $constructor:expr) => {
+        test_schemer_adapter!(@suite [] $setup, $constructor);
+    };
     ($setup:stmt, $constructor:expr, $($test_fn:ident),* $(,)*) => {
-        $(
-            #[test]
-            fn $test_fn() {
-                $setup
-                let adapter = $constructor;
-                $crate::testing::$test_fn(adapter);
-            }
-        )*
-    }
+        test_schemer_adapter!(@tests [] $setup, $constructor, $($test_fn),*);
+    };
+}
+
+/// Name of a metadata table for use by a single test, for adapters whose tests
+/// share a database.
+pub fn unique_table_name() -> String {
+    format!("_schemer_{}", Uuid::new_v4().simple())
 }
 
 /// Test the application and reversion of a singleton migration.
@@ -296,3 +365,929 @@ pub fn test_branching_dag<A: TestAdapter>(adapter: A) {
         assert!(!applied.contains(&uuid5));
     }
 }
+
+/// Test that planning lists migrations in application order without applying
+/// or reverting them.
+pub fn test_plan<A: TestAdapter>(adapter: A) {
+    let migration1 = A::mock(
+        Uuid::parse_str("bc960dc8-0e4a-4182-a62a-8e776d1e2b30").unwrap(),
+        HashSet::new(),
+    );
+    let migration2 = A::mock(
+        Uuid::parse_str("4885e8ab-dafa-4d76-a565-2dee8b04ef60").unwrap(),
+        vec![migration1.id()].into_iter().collect(),
+    );
+    let migration3 = A::mock(
+        Uuid::parse_str("c5d07448-851f-45e8-8fa7-4823d5250609").unwrap(),
+        vec![migration2.id()].into_iter().collect(),
+    );
+
+    let uuid1 = migration1.id();
+    let uuid2 = migration2.id();
+    let uuid3 = migration3.id();
+
+    let mut migrator = Migrator::new(adapter);
+
+    migrator
+        .register_multiple(vec![migration1, migration2, migration3])
+        .expect("Migration registration failed");
+
+    let plan = migrator.plan_up(Some(uuid2)).expect("Up plan failed");
+    assert_eq!(
+        plan.iter().map(|p| p.id).collect::<Vec<_>>(),
+        vec![uuid1, uuid2]
+    );
+    assert!(plan.iter().all(|p| p.direction == MigrationDirection::Up));
+    assert!(migrator.adapter.applied_migrations().unwrap().is_empty());
+
+    migrator.up(None).expect("Up migration failed");
+    assert!(migrator.plan_up(None).expect("Up plan failed").is_empty());
+
+    let plan = migrator.plan_down(Some(uuid1)).expect("Down plan failed");
+    assert_eq!(
+        plan.iter().map(|p| p.id).collect::<Vec<_>>(),
+        vec![uuid3, uuid2]
+    );
+    assert!(plan.iter().all(|p| p.direction == MigrationDirection::Down));
+    assert_eq!(migrator.adapter.applied_migrations().unwrap().len(), 3);
+}
+
+/// Test classification of applied, pending and orphaned migrations.
+pub fn test_status<A: TestAdapter>(adapter: A) {
+    let migration1 = A::mock(
+        Uuid::parse_str("bc960dc8-0e4a-4182-a62a-8e776d1e2b30").unwrap(),
+        HashSet::new(),
+    );
+    let migration2 = A::mock(
+        Uuid::parse_str("4885e8ab-dafa-4d76-a565-2dee8b04ef60").unwrap(),
+        vec![migration1.id()].into_iter().collect(),
+    );
+    let migration3 = A::mock(
+        Uuid::parse_str("c5d07448-851f-45e8-8fa7-4823d5250609").unwrap(),
+        vec![migration2.id()].into_iter().collect(),
+    );
+
+    let uuid1 = migration1.id();
+    let uuid2 = migration2.id();
+    let uuid3 = migration3.id();
+
+    let mut migrator = Migrator::new(adapter);
+
+    migrator
+        .register_multiple(vec![migration1, migration2, migration3])
+        .expect("Migration registration failed");
+    migrator.up(None).expect("Up migration failed");
+
+    // Simulate an older version of the application that only knows of the
+    // first two migrations, plus a new one of its own.
+    let migration4 = A::mock(
+        Uuid::parse_str("9433a432-386f-467e-a59f-a9fb7e249767").unwrap(),
+        vec![uuid2].into_iter().collect(),
+    );
+    let uuid4 = migration4.id();
+
+    let mut migrator = Migrator::new(migrator.adapter);
+    migrator
+        .register_multiple(vec![
+            A::mock(uuid1, HashSet::new()),
+            A::mock(uuid2, vec![uuid1].into_iter().collect()),
+            migration4,
+        ])
+        .expect("Migration registration failed");
+
+    let status = migrator.status().expect("Status failed");
+    assert_eq!(status.applied, vec![uuid1, uuid2]);
+    assert_eq!(status.pending, vec![uuid4]);
+    assert_eq!(status.orphaned, vec![uuid3]);
+}
+
+/// Test detection of applied migrations inconsistent with the dependency
+/// graph, and that strict mode refuses to migrate on top of them.
+pub fn test_validate<A: TestAdapter>(adapter: A) {
+    let migration1 = A::mock(
+        Uuid::parse_str("bc960dc8-0e4a-4182-a62a-8e776d1e2b30").unwrap(),
+        HashSet::new(),
+    );
+    let migration2 = A::mock(
+        Uuid::parse_str("4885e8ab-dafa-4d76-a565-2dee8b04ef60").unwrap(),
+        vec![migration1.id()].into_iter().collect(),
+    );
+    let migration3 = A::mock(
+        Uuid::parse_str("c5d07448-851f-45e8-8fa7-4823d5250609").unwrap(),
+        vec![migration2.id()].into_iter().collect(),
+    );
+
+    let uuid1 = migration1.id();
+    let uuid2 = migration2.id();
+
+    let mut migrator = Migrator::new(adapter);
+
+    migrator
+        .register_multiple(vec![migration1, migration2, migration3])
+        .expect("Migration registration failed");
+    migrator.validate().expect("Validation failed");
+
+    // Apply the second migration without its dependency.
+    migrator
+        .adapter
+        .apply_migration(&migrator.graph.dependencies[migrator.graph.id_map[&uuid2]])
+        .expect("Adapter migration failed");
+
+    match migrator.validate() {
+        Err(MigratorError::Dependency(DependencyError::UnappliedDependency { id, dependency })) => {
+            assert_eq!(id, uuid2);
+            assert_eq!(dependency, uuid1);
+        }
+        _ => panic!("Validation did not detect unapplied dependency"),
+    }
+
+    migrator.set_strict(true);
+    assert!(migrator.plan_up(None).is_err());
+    assert!(migrator.up(None).is_err());
+    assert!(migrator.down(None).is_err());
+    assert!(migrator
+        .adapter
+        .applied_migrations()
+        .unwrap()
+        .contains(&uuid2));
+    assert!(!migrator
+        .adapter
+        .applied_migrations()
+        .unwrap()
+        .contains(&uuid1));
+
+    // A migrator that does not know of the applied migration reports it as
+    // unknown.
+    let mut migrator = Migrator::new(migrator.adapter);
+    migrator
+        .register(A::mock(uuid1, HashSet::new()))
+        .expect("Migration registration failed");
+
+    match migrator.validate() {
+        Err(MigratorError::Dependency(DependencyError::UnknownApplied(id))) => {
+            assert_eq!(id, uuid2);
+        }
+        _ => panic!("Validation did not detect unknown applied migration"),
+    }
+}
+
+/// Test that adapters store checksums and that changed migrations are
+/// reported and refused in strict mode.
+pub fn test_checksum<A: TestAdapter>(adapter: A) {
+    let uuid1 = Uuid::parse_str("bc960dc8-0e4a-4182-a62a-8e776d1e2b30").unwrap();
+    let uuid2 = Uuid::parse_str("4885e8ab-dafa-4d76-a565-2dee8b04ef60").unwrap();
+    let uuid3 = Uuid::parse_str("c5d07448-851f-45e8-8fa7-4823d5250609").unwrap();
+
+    let mut migrator = Migrator::new(adapter);
+
+    migrator
+        .register_multiple(vec![
+            A::mock_from(TestMigration::new(uuid1, HashSet::new()).with_checksum("one")),
+            A::mock_from(
+                TestMigration::new(uuid2, vec![uuid1].into_iter().collect()).with_checksum("two"),
+            ),
+            A::mock(uuid3, vec![uuid2].into_iter().collect()),
+        ])
+        .expect("Migration registration failed");
+    migrator.up(None).expect("Up migration failed");
+
+    let checksums = migrator.adapter.applied_checksums().unwrap();
+    assert_eq!(checksums.len(), 2);
+    assert_eq!(checksums[&uuid1], "one");
+    assert_eq!(checksums[&uuid2], "two");
+    assert!(migrator.checksum_mismatches().unwrap().is_empty());
+    migrator.validate().expect("Validation failed");
+
+    // Edit the second migration and stop checksumming the first.
+    let mut migrator = Migrator::new(migrator.adapter);
+    migrator
+        .register_multiple(vec![
+            A::mock(uuid1, HashSet::new()),
+            A::mock_from(
+                TestMigration::new(uuid2, vec![uuid1].into_iter().collect())
+                    .with_checksum("edited"),
+            ),
+            A::mock(uuid3, vec![uuid2].into_iter().collect()),
+        ])
+        .expect("Migration registration failed");
+
+    assert_eq!(
+        migrator.checksum_mismatches().unwrap(),
+        vec![
+            ChecksumMismatch {
+                id: uuid2,
+                stored: "two".into(),
+                current: Some("edited".into()),
+            },
+            ChecksumMismatch {
+                id: uuid1,
+                stored: "one".into(),
+                current: None,
+            },
+        ]
+    );
+
+    match migrator.validate() {
+        Err(MigratorError::Dependency(DependencyError::ChecksumMismatch { id, .. })) => {
+            assert_eq!(id, uuid2);
+        }
+        _ => panic!("Validation did not detect changed checksum"),
+    }
+
+    migrator.set_strict(true);
+    assert!(migrator.down(None).is_err());
+    assert_eq!(migrator.adapter.applied_migrations().unwrap().len(), 3);
+}
+
+/// Test that applied migrations are listed with their metadata.
+pub fn test_applied_records<A: TestAdapter>(adapter: A) {
+    let uuid1 = Uuid::parse_str("bc960dc8-0e4a-4182-a62a-8e776d1e2b30").unwrap();
+    let uuid2 = Uuid::parse_str("4885e8ab-dafa-4d76-a565-2dee8b04ef60").unwrap();
+    let uuid3 = Uuid::parse_str("c5d07448-851f-45e8-8fa7-4823d5250609").unwrap();
+
+    let mut migrator = Migrator::new(adapter);
+
+    migrator
+        .register_multiple(vec![
+            A::mock_from(TestMigration::new(uuid1, HashSet::new()).with_checksum("one")),
+            A::mock(uuid2, vec![uuid1].into_iter().collect()),
+            A::mock(uuid3, vec![uuid2].into_iter().collect()),
+        ])
+        .expect("Migration registration failed");
+    assert!(migrator.applied_records().unwrap().is_empty());
+
+    migrator.up(Some(uuid2)).expect("Up migration failed");
+    let mut records = migrator.applied_records().unwrap();
+    records.sort_by_key(|record| record.id);
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0].id, uuid2);
+    assert_eq!(records[0].checksum, None);
+    assert_eq!(records[1].id, uuid1);
+    assert_eq!(records[1].checksum.as_deref(), Some("one"));
+
+    migrator.down(Some(uuid1)).expect("Down migration failed");
+    let records = migrator.applied_records().unwrap();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].id, uuid1);
+}
+
+/// Test that up and down report executed and skipped migrations.
+pub fn test_report<A: TestAdapter>(adapter: A) {
+    let uuid1 = Uuid::parse_str("bc960dc8-0e4a-4182-a62a-8e776d1e2b30").unwrap();
+    let uuid2 = Uuid::parse_str("4885e8ab-dafa-4d76-a565-2dee8b04ef60").unwrap();
+    let uuid3 = Uuid::parse_str("c5d07448-851f-45e8-8fa7-4823d5250609").unwrap();
+
+    let mut migrator = Migrator::new(adapter);
+
+    migrator
+        .register_multiple(vec![
+            A::mock(uuid1, HashSet::new()),
+            A::mock(uuid2, vec![uuid1].into_iter().collect()),
+            A::mock(uuid3, vec![uuid2].into_iter().collect()),
+        ])
+        .expect("Migration registration failed");
+
+    let report = migrator.up(Some(uuid1)).expect("Up migration failed");
+    assert_eq!(report.direction, MigrationDirection::Up);
+    assert_eq!(
+        report.executed.iter().map(|m| m.id).collect::<Vec<_>>(),
+        vec![uuid1]
+    );
+    assert!(report.skipped.is_empty());
+
+    let report = migrator.up(None).expect("Up migration failed");
+    assert_eq!(
+        report.executed.iter().map(|m| m.id).collect::<Vec<_>>(),
+        vec![uuid2, uuid3]
+    );
+    assert!(report
+        .executed
+        .iter()
+        .all(|m| m.direction == MigrationDirection::Up));
+    assert_eq!(report.skipped, vec![uuid1]);
+
+    migrator.down(Some(uuid2)).expect("Down migration failed");
+    let report = migrator.down(None).expect("Down migration failed");
+    assert_eq!(report.direction, MigrationDirection::Down);
+    assert_eq!(
+        report.executed.iter().map(|m| m.id).collect::<Vec<_>>(),
+        vec![uuid2, uuid1]
+    );
+    assert_eq!(report.skipped, vec![uuid3]);
+}
+
+/// Observer recording each event it is notified of as a line of text.
+struct RecordingObserver(Arc<Mutex<Vec<String>>>);
+
+impl MigrationObserver for RecordingObserver {
+    fn on_plan(&mut self, plan: &[PlannedMigration]) {
+        self.0.lock().unwrap().push(format!("plan {}", plan.len()));
+    }
+
+    fn before_migration(&mut self, migration: &PlannedMigration) {
+        self.0
+            .lock()
+            .unwrap()
+            .push(format!("before {} {:?}", migration.id, migration.direction));
+    }
+
+    fn after_migration(&mut self, migration: &ExecutedMigration) {
+        self.0
+            .lock()
+            .unwrap()
+            .push(format!("after {} {:?}", migration.id, migration.direction));
+    }
+
+    fn on_error(&mut self, migration: Option<&PlannedMigration>, _error: &dyn std::error::Error) {
+        self.0.lock().unwrap().push(match migration {
+            Some(migration) => format!("error {}", migration.id),
+            None => "error".to_owned(),
+        });
+    }
+
+    fn on_complete(&mut self, report: &MigrationReport) {
+        self.0
+            .lock()
+            .unwrap()
+            .push(format!("complete {}", report.executed.len()));
+    }
+}
+
+/// Test that observers are notified of each stage of migrating.
+pub fn test_observer<A: TestAdapter>(adapter: A) {
+    let uuid1 = Uuid::parse_str("bc960dc8-0e4a-4182-a62a-8e776d1e2b30").unwrap();
+    let uuid2 = Uuid::parse_str("4885e8ab-dafa-4d76-a565-2dee8b04ef60").unwrap();
+
+    let events = Arc::new(Mutex::new(vec![]));
+    let mut migrator = Migrator::new(adapter);
+    migrator.add_observer(Box::new(RecordingObserver(events.clone())));
+
+    migrator
+        .register_multiple(vec![
+            A::mock(uuid1, HashSet::new()),
+            A::mock(uuid2, vec![uuid1].into_iter().collect()),
+        ])
+        .expect("Migration registration failed");
+
+    migrator.up(None).expect("Up migration failed");
+    migrator.down(Some(uuid1)).expect("Down migration failed");
+
+    assert_eq!(
+        *events.lock().unwrap(),
+        vec![
+            "plan 2".to_owned(),
+            format!("before {} Up", uuid1),
+            format!("after {} Up", uuid1),
+            format!("before {} Up", uuid2),
+            format!("after {} Up", uuid2),
+            "complete 2".to_owned(),
+            "plan 1".to_owned(),
+            format!("before {} Down", uuid2),
+            format!("after {} Down", uuid2),
+            "complete 1".to_owned(),
+        ]
+    );
+
+    // Observers are also notified when a run fails before executing any
+    // migration, here because one to revert is irreversible.
+    let uuid3 = Uuid::parse_str("c5d07448-851f-45e8-8fa7-4823d5250609").unwrap();
+    migrator
+        .register(A::mock_from(
+            TestMigration::new(uuid3, vec![uuid2].into_iter().collect()).irreversible(),
+        ))
+        .expect("Migration registration failed");
+    migrator.up(None).expect("Up migration failed");
+    events.lock().unwrap().clear();
+
+    assert!(migrator.down(None).is_err());
+    assert_eq!(*events.lock().unwrap(), vec!["error".to_owned()]);
+}
+
+/// Test that reverting an irreversible migration is refused before any
+/// migrations are reverted.
+pub fn test_irreversible<A: TestAdapter>(adapter: A) {
+    let uuid1 = Uuid::parse_str("bc960dc8-0e4a-4182-a62a-8e776d1e2b30").unwrap();
+    let uuid2 = Uuid::parse_str("4885e8ab-dafa-4d76-a565-2dee8b04ef60").unwrap();
+    let uuid3 = Uuid::parse_str("c5d07448-851f-45e8-8fa7-4823d5250609").unwrap();
+
+    let mut migrator = Migrator::new(adapter);
+
+    migrator
+        .register_multiple(vec![
+            A::mock(uuid1, HashSet::new()),
+            A::mock_from(
+                TestMigration::new(uuid2, vec![uuid1].into_iter().collect()).irreversible(),
+            ),
+            A::mock(uuid3, vec![uuid2].into_iter().collect()),
+        ])
+        .expect("Migration registration failed");
+    migrator.up(None).expect("Up migration failed");
+
+    match migrator.down(None) {
+        Err(MigratorError::Irreversible { id, .. }) => assert_eq!(id, uuid2),
+        _ => panic!("Down migration did not refuse irreversible migration"),
+    }
+    assert!(migrator.plan_down(None).is_err());
+    assert_eq!(migrator.adapter.applied_migrations().unwrap().len(), 3);
+
+    migrator.down(Some(uuid2)).expect("Down migration failed");
+
+    {
+        let applied = migrator.adapter.applied_migrations().unwrap();
+        assert!(applied.contains(&uuid1));
+        assert!(applied.contains(&uuid2));
+        assert!(!applied.contains(&uuid3));
+    }
+}
+
+/// Test recording migrations as applied or reverted without executing them.
+pub fn test_mark<A: TestAdapter>(adapter: A) {
+    let uuid1 = Uuid::parse_str("bc960dc8-0e4a-4182-a62a-8e776d1e2b30").unwrap();
+    let uuid2 = Uuid::parse_str("4885e8ab-dafa-4d76-a565-2dee8b04ef60").unwrap();
+    let uuid3 = Uuid::parse_str("c5d07448-851f-45e8-8fa7-4823d5250609").unwrap();
+    let unknown = Uuid::parse_str("0940acb1-0e2e-4b99-9d69-2302a9c74524").unwrap();
+
+    let mut migrator = Migrator::new(adapter);
+
+    migrator
+        .register_multiple(vec![
+            A::mock(uuid1, HashSet::new()),
+            A::mock(uuid2, vec![uuid1].into_iter().collect()),
+            A::mock(uuid3, vec![uuid2].into_iter().collect()),
+        ])
+        .expect("Migration registration failed");
+
+    migrator
+        .mark_applied(&[uuid2, uuid1])
+        .expect("Marking applied failed");
+    migrator
+        .mark_applied(&[uuid1])
+        .expect("Marking applied failed");
+
+    {
+        let applied = migrator.adapter.applied_migrations().unwrap();
+        assert!(applied.contains(&uuid1));
+        assert!(applied.contains(&uuid2));
+        assert!(!applied.contains(&uuid3));
+    }
+
+    let report = migrator.up(None).expect("Up migration failed");
+    assert_eq!(
+        report.executed.iter().map(|m| m.id).collect::<Vec<_>>(),
+        vec![uuid3]
+    );
+
+    migrator
+        .mark_reverted(&[uuid3, uuid2])
+        .expect("Marking reverted failed");
+
+    {
+        let applied = migrator.adapter.applied_migrations().unwrap();
+        assert!(applied.contains(&uuid1));
+        assert!(!applied.contains(&uuid2));
+        assert!(!applied.contains(&uuid3));
+    }
+
+    match migrator.mark_applied(&[uuid2, unknown]) {
+        Err(MigratorError::Dependency(DependencyError::UnknownId(id))) => assert_eq!(id, unknown),
+        _ => panic!("Marking did not detect unknown ID"),
+    }
+    assert!(!migrator
+        .adapter
+        .applied_migrations()
+        .unwrap()
+        .contains(&uuid2));
+}
+
+#[cfg(feature = "async")]
+pub use self::async_tests::*;
+
+/// Generic test suite for `AsyncAdapter`s, mirroring the suite for `Adapter`s.
+#[cfg(feature = "async")]
+mod async_tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::super::*;
+    use super::{RecordingObserver, TestMigration};
+
+    /// A trait required for running the generic async test suite on an
+    /// `AsyncAdapter`.
+    pub trait AsyncTestAdapter: AsyncAdapter {
+        /// Construct a mock, no-op migration of the adapter's `MigrationType`
+        /// from a `TestMigration`.
+        fn mock_from(migration: TestMigration) -> Box<Self::MigrationType>;
+
+        /// Construct a mock, no-op migration of the adapter's `MigrationType`.
+        fn mock(id: Uuid, dependencies: HashSet<Uuid>) -> Box<Self::MigrationType> {
+            Self::mock_from(TestMigration::new(id, dependencies))
+        }
+    }
+
+    /// Test the application and reversion of a singleton migration.
+    pub async fn test_single_migration_async<A: AsyncTestAdapter>(adapter: A) {
+        let migration1 = A::mock(
+            Uuid::parse_str("bc960dc8-0e4a-4182-a62a-8e776d1e2b30").unwrap(),
+            HashSet::new(),
+        );
+        let uuid1 = migration1.id();
+
+        let mut migrator: AsyncMigrator<A> = AsyncMigrator::new(adapter);
+
+        migrator
+            .register(migration1)
+            .expect("Migration 1 registration failed");
+        migrator.up(None).await.expect("Up migration failed");
+
+        assert!(migrator
+            .adapter
+            .applied_migrations()
+            .await
+            .unwrap()
+            .contains(&uuid1));
+
+        migrator.down(None).await.expect("Down migration failed");
+
+        assert!(!migrator
+            .adapter
+            .applied_migrations()
+            .await
+            .unwrap()
+            .contains(&uuid1));
+    }
+
+    /// Test application and reversion on a branching DAG, including the
+    /// reports of executed and skipped migrations.
+    pub async fn test_branching_dag_async<A: AsyncTestAdapter>(adapter: A) {
+        let uuid1 = Uuid::parse_str("bc960dc8-0e4a-4182-a62a-8e776d1e2b30").unwrap();
+        let uuid2 = Uuid::parse_str("4885e8ab-dafa-4d76-a565-2dee8b04ef60").unwrap();
+        let uuid3 = Uuid::parse_str("c5d07448-851f-45e8-8fa7-4823d5250609").unwrap();
+        let uuid4 = Uuid::parse_str("9433a432-386f-467e-a59f-a9fb7e249767").unwrap();
+        let uuid5 = Uuid::parse_str("0940acb1-0e2e-4b99-9d69-2302a9c74524").unwrap();
+
+        let mut migrator = AsyncMigrator::new(adapter);
+
+        migrator
+            .register_multiple(vec![
+                A::mock(uuid1, HashSet::new()),
+                A::mock(uuid2, HashSet::new()),
+                A::mock(uuid3, vec![uuid1, uuid2].into_iter().collect()),
+                A::mock(uuid4, vec![uuid3].into_iter().collect()),
+                A::mock(uuid5, vec![uuid3].into_iter().collect()),
+            ])
+            .expect("Migration registration failed");
+
+        let report = migrator.up(Some(uuid4)).await.expect("Up migration failed");
+        assert_eq!(
+            report.executed.iter().map(|m| m.id).collect::<Vec<_>>(),
+            vec![uuid1, uuid2, uuid3, uuid4]
+        );
+
+        {
+            let applied = migrator.adapter.applied_migrations().await.unwrap();
+            assert!(applied.contains(&uuid4));
+            assert!(!applied.contains(&uuid5));
+        }
+
+        let report = migrator
+            .down(Some(uuid1))
+            .await
+            .expect("Down migration failed");
+        assert_eq!(
+            report.executed.iter().map(|m| m.id).collect::<Vec<_>>(),
+            vec![uuid4, uuid3]
+        );
+        assert_eq!(report.skipped, vec![uuid5]);
+
+        {
+            let applied = migrator.adapter.applied_migrations().await.unwrap();
+            assert!(applied.contains(&uuid1));
+            assert!(applied.contains(&uuid2));
+            assert!(!applied.contains(&uuid3));
+            assert!(!applied.contains(&uuid4));
+        }
+    }
+
+    /// Test planning and status without applying or reverting migrations.
+    pub async fn test_plan_async<A: AsyncTestAdapter>(adapter: A) {
+        let uuid1 = Uuid::parse_str("bc960dc8-0e4a-4182-a62a-8e776d1e2b30").unwrap();
+        let uuid2 = Uuid::parse_str("4885e8ab-dafa-4d76-a565-2dee8b04ef60").unwrap();
+        let uuid3 = Uuid::parse_str("c5d07448-851f-45e8-8fa7-4823d5250609").unwrap();
+
+        let mut migrator = AsyncMigrator::new(adapter);
+
+        migrator
+            .register_multiple(vec![
+                A::mock(uuid1, HashSet::new()),
+                A::mock(uuid2, vec![uuid1].into_iter().collect()),
+                A::mock(uuid3, vec![uuid2].into_iter().collect()),
+            ])
+            .expect("Migration registration failed");
+
+        let plan = migrator.plan_up(Some(uuid2)).await.expect("Up plan failed");
+        assert_eq!(
+            plan.iter().map(|p| p.id).collect::<Vec<_>>(),
+            vec![uuid1, uuid2]
+        );
+        assert!(migrator
+            .adapter
+            .applied_migrations()
+            .await
+            .unwrap()
+            .is_empty());
+
+        migrator.up(Some(uuid2)).await.expect("Up migration failed");
+        let status = migrator.status().await.expect("Status failed");
+        assert_eq!(status.applied, vec![uuid1, uuid2]);
+        assert_eq!(status.pending, vec![uuid3]);
+
+        let plan = migrator
+            .plan_down(Some(uuid1))
+            .await
+            .expect("Down plan failed");
+        assert_eq!(plan.iter().map(|p| p.id).collect::<Vec<_>>(), vec![uuid2]);
+        migrator.validate().await.expect("Validation failed");
+    }
+
+    /// Test recording migrations as applied or reverted without running them.
+    pub async fn test_mark_async<A: AsyncTestAdapter>(adapter: A) {
+        let uuid1 = Uuid::parse_str("bc960dc8-0e4a-4182-a62a-8e776d1e2b30").unwrap();
+        let uuid2 = Uuid::parse_str("4885e8ab-dafa-4d76-a565-2dee8b04ef60").unwrap();
+
+        let mut migrator = AsyncMigrator::new(adapter);
+
+        migrator
+            .register_multiple(vec![
+                A::mock(uuid1, HashSet::new()),
+                A::mock(uuid2, vec![uuid1].into_iter().collect()),
+            ])
+            .expect("Migration registration failed");
+
+        migrator
+            .mark_applied(&[uuid1])
+            .await
+            .expect("Marking applied failed");
+        let report = migrator.up(None).await.expect("Up migration failed");
+        assert_eq!(report.skipped, vec![uuid1]);
+        assert_eq!(
+            report.executed.iter().map(|m| m.id).collect::<Vec<_>>(),
+            vec![uuid2]
+        );
+
+        migrator
+            .mark_reverted(&[uuid1, uuid2])
+            .await
+            .expect("Marking reverted failed");
+        assert!(migrator
+            .adapter
+            .applied_migrations()
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    /// Test that adapters store checksums and that changed migrations are
+    /// reported and refused in strict mode.
+    pub async fn test_checksum_async<A: AsyncTestAdapter>(adapter: A) {
+        let uuid1 = Uuid::parse_str("bc960dc8-0e4a-4182-a62a-8e776d1e2b30").unwrap();
+        let uuid2 = Uuid::parse_str("4885e8ab-dafa-4d76-a565-2dee8b04ef60").unwrap();
+
+        let mut migrator = AsyncMigrator::new(adapter);
+
+        migrator
+            .register_multiple(vec![
+                A::mock_from(TestMigration::new(uuid1, HashSet::new()).with_checksum("one")),
+                A::mock(uuid2, vec![uuid1].into_iter().collect()),
+            ])
+            .expect("Migration registration failed");
+        migrator.up(None).await.expect("Up migration failed");
+
+        let checksums = migrator.adapter.applied_checksums().await.unwrap();
+        assert_eq!(checksums.len(), 1);
+        assert_eq!(checksums[&uuid1], "one");
+        assert!(migrator.checksum_mismatches().await.unwrap().is_empty());
+
+        // Edit the first migration.
+        let mut migrator = AsyncMigrator::new(migrator.adapter);
+        migrator
+            .register_multiple(vec![
+                A::mock_from(TestMigration::new(uuid1, HashSet::new()).with_checksum("edited")),
+                A::mock(uuid2, vec![uuid1].into_iter().collect()),
+            ])
+            .expect("Migration registration failed");
+
+        assert_eq!(
+            migrator.checksum_mismatches().await.unwrap(),
+            vec![ChecksumMismatch {
+                id: uuid1,
+                stored: "one".into(),
+                current: Some("edited".into()),
+            }]
+        );
+        match migrator.validate().await {
+            Err(MigratorError::Dependency(DependencyError::ChecksumMismatch { id, .. })) => {
+                assert_eq!(id, uuid1);
+            }
+            _ => panic!("Validation did not detect changed checksum"),
+        }
+
+        migrator.set_strict(true);
+        assert!(migrator.down(None).await.is_err());
+        assert_eq!(
+            migrator.adapter.applied_migrations().await.unwrap().len(),
+            2
+        );
+    }
+
+    /// Test that strict mode refuses to migrate on top of applied migrations
+    /// inconsistent with the dependency graph.
+    pub async fn test_strict_async<A: AsyncTestAdapter>(adapter: A) {
+        let uuid1 = Uuid::parse_str("bc960dc8-0e4a-4182-a62a-8e776d1e2b30").unwrap();
+        let uuid2 = Uuid::parse_str("4885e8ab-dafa-4d76-a565-2dee8b04ef60").unwrap();
+
+        let mut migrator = AsyncMigrator::new(adapter);
+
+        migrator
+            .register_multiple(vec![
+                A::mock(uuid1, HashSet::new()),
+                A::mock(uuid2, vec![uuid1].into_iter().collect()),
+            ])
+            .expect("Migration registration failed");
+
+        // Record the second migration without its dependency.
+        migrator
+            .mark_applied(&[uuid2])
+            .await
+            .expect("Marking applied failed");
+
+        match migrator.validate().await {
+            Err(MigratorError::Dependency(DependencyError::UnappliedDependency {
+                id,
+                dependency,
+            })) => {
+                assert_eq!(id, uuid2);
+                assert_eq!(dependency, uuid1);
+            }
+            _ => panic!("Validation did not detect unapplied dependency"),
+        }
+
+        migrator.set_strict(true);
+        assert!(migrator.plan_up(None).await.is_err());
+        assert!(migrator.up(None).await.is_err());
+        assert!(migrator.down(None).await.is_err());
+        assert_eq!(
+            migrator.adapter.applied_migrations().await.unwrap(),
+            vec![uuid2].into_iter().collect()
+        );
+    }
+
+    /// Test that reverting an irreversible migration is refused before any
+    /// migrations are reverted.
+    pub async fn test_irreversible_async<A: AsyncTestAdapter>(adapter: A) {
+        let uuid1 = Uuid::parse_str("bc960dc8-0e4a-4182-a62a-8e776d1e2b30").unwrap();
+        let uuid2 = Uuid::parse_str("4885e8ab-dafa-4d76-a565-2dee8b04ef60").unwrap();
+
+        let mut migrator = AsyncMigrator::new(adapter);
+
+        migrator
+            .register_multiple(vec![
+                A::mock_from(TestMigration::new(uuid1, HashSet::new()).irreversible()),
+                A::mock(uuid2, vec![uuid1].into_iter().collect()),
+            ])
+            .expect("Migration registration failed");
+        migrator.up(None).await.expect("Up migration failed");
+
+        match migrator.down(None).await {
+            Err(MigratorError::Irreversible { id, .. }) => assert_eq!(id, uuid1),
+            _ => panic!("Down migration did not refuse irreversible migration"),
+        }
+        assert!(migrator.plan_down(None).await.is_err());
+        assert_eq!(
+            migrator.adapter.applied_migrations().await.unwrap().len(),
+            2
+        );
+
+        migrator
+            .down(Some(uuid1))
+            .await
+            .expect("Down migration failed");
+        assert_eq!(
+            migrator.adapter.applied_migrations().await.unwrap(),
+            vec![uuid1].into_iter().collect()
+        );
+    }
+
+    /// Test that observers are notified of each stage of migrating, and of
+    /// runs that fail before any migration is executed.
+    pub async fn test_observer_async<A: AsyncTestAdapter>(adapter: A) {
+        let uuid1 = Uuid::parse_str("bc960dc8-0e4a-4182-a62a-8e776d1e2b30").unwrap();
+        let uuid2 = Uuid::parse_str("4885e8ab-dafa-4d76-a565-2dee8b04ef60").unwrap();
+
+        let events = Arc::new(Mutex::new(vec![]));
+        let mut migrator = AsyncMigrator::new(adapter);
+        migrator.add_observer(Box::new(RecordingObserver(events.clone())));
+
+        migrator
+            .register_multiple(vec![
+                A::mock_from(TestMigration::new(uuid1, HashSet::new()).irreversible()),
+                A::mock(uuid2, vec![uuid1].into_iter().collect()),
+            ])
+            .expect("Migration registration failed");
+
+        migrator.up(None).await.expect("Up migration failed");
+        migrator
+            .down(Some(uuid1))
+            .await
+            .expect("Down migration failed");
+        assert!(migrator.down(None).await.is_err());
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                "plan 2".to_owned(),
+                format!("before {} Up", uuid1),
+                format!("after {} Up", uuid1),
+                format!("before {} Up", uuid2),
+                format!("after {} Up", uuid2),
+                "complete 2".to_owned(),
+                "plan 1".to_owned(),
+                format!("before {} Down", uuid2),
+                format!("after {} Down", uuid2),
+                "complete 1".to_owned(),
+                "error".to_owned(),
+            ]
+        );
+    }
+}
+
+/// Test an `AsyncAdapter` with the generic async test suite.
+///
+/// The first argument is the path of a function that runs a future to
+/// completion, such as `futures_executor::block_on` or a wrapper around a
+/// tokio runtime. The setup statement and constructor expression are run
+/// inside the future, so the constructor may `.await`. As with
+/// `test_schemer_adapter!`, an attribute before the setup statement is applied
+/// to each generated test.
+///
+/// Note that the adapter must also implement the `AsyncTestAdapter` trait.
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// #[macro_use] extern crate schemer;
+///
+/// async fn construct_my_adapter_test_fixture() -> MyAdapterType {
+///     MyAdapterType {}
+/// }
+///
+/// test_schemer_async_adapter!(
+///     futures_executor::block_on,
+///     construct_my_adapter_test_fixture().await
+/// );
+/// ```
+#[cfg(feature = "async")]
+#[macro_export]
+macro_rules! test_schemer_async_adapter {
+    (@tests $block_on:path, $attrs:tt $setup:stmt, $constructor:expr, $($test_fn:ident),* $(,)*) => {
+        $(
+            test_schemer_async_adapter!(@test $block_on, $attrs $setup, $constructor, $test_fn);
+        )*
+    };
+    (@test $block_on:path, [$($attr:tt)*] $setup:stmt, $constructor:expr, $test_fn:ident) => {
+        #[test]
+        $($attr)*
+        fn $test_fn() {
+            $block_on(async {
+                $setup
+                let adapter = $constructor;
+                $crate::testing::$test_fn(adapter).await;
+            });
+        }
+    };
+    (@suite $block_on:path, $attrs:tt $setup:stmt, $constructor:expr) => {
+        test_schemer_async_adapter!(@tests $block_on, $attrs $setup, $constructor,
+            test_single_migration_async,
+            test_branching_dag_async,
+            test_plan_async,
+            test_mark_async,
+            test_checksum_async,
+            test_strict_async,
+            test_irreversible_async,
+            test_observer_async,
+        );
+    };
+    ($block_on:path, #[$attr:meta] $setup:stmt, $constructor:expr) => {
+        test_schemer_async_adapter!(@suite $block_on, [#[$attr]] $setup, $constructor);
+    };
+    ($block_on:path, #[$attr:meta] $setup:stmt, $constructor:expr, $($test_fn:ident),* $(,)*) => {
+        test_schemer_async_adapter!(@tests $block_on, [#[$attr]] $setup, $constructor, $($test_fn),*);
+    };
+    ($block_on:path, $constructor:expr) => {
+        test_schemer_async_adapter!($block_on, {}, $constructor);
+    };
+    ($block_on:path, $setup:stmt, $constructor:expr) => {
+        test_schemer_async_adapter!(@suite $block_on, [] $setup, $constructor);
+    };
+    ($block_on:path, $setup:stmt, $constructor:expr, $($test_fn:ident),* $(,)*) => {
+        test_schemer_async_adapter!(@tests $block_on, [] $setup, $constructor, $($test_fn),*);
+    };
+}